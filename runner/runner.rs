@@ -1,8 +1,39 @@
 use std::io::prelude::*;
+use std::io::BufReader;
 use std::net::TcpListener;
 use std::net::TcpStream;
 use std::env;
-use std::process::Command;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// Handed to the solver so a bad position can't keep a connection open forever.
+const SOLVE_TIME_LIMIT_MS: u32 = 20000;
+
+/// The solver gets every core of the host, one solve runs at a time.
+fn solve_threads() -> usize {
+    thread::available_parallelism().map_or(1, |threads| threads.get())
+}
+
+/// Requests starting with this get every improved line as soon as it is found, one per line,
+/// instead of just the final one.
+const ANYTIME_PREFIX: &str = "anytime ";
+
+/// Generated with `gen_tablebase`, used by the solver if it's there. Mapped by the solver and
+/// checked a bucket at a time, so only the pages a solve touches get read.
+const TABLEBASE_PATH: &str = "/root/d15.tablebase";
+
+/// The solver with the flags every request gets.
+fn solver_command() -> Command {
+    let mut command = Command::new("/root/ed15r");
+    command
+        .arg(format!("--time-limit-ms={}", SOLVE_TIME_LIMIT_MS))
+        .arg(format!("--threads={}", solve_threads()));
+    if Path::new(TABLEBASE_PATH).exists() {
+        command.arg(format!("--tablebase={}", TABLEBASE_PATH));
+    }
+    command
+}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -27,18 +58,32 @@ fn handle_connection(mut stream: TcpStream) {
     let input_string = String::from_utf8(buffer.to_vec()).unwrap();
     let input_str = input_string.as_str().trim_matches(char::from(0)).trim();
 
-    let output = Command::new("/root/ed15r")
+    let (anytime, input_str) = match input_str.strip_prefix(ANYTIME_PREFIX) {
+        Some(rest) => (true, rest.trim()),
+        None => (false, input_str),
+    };
+    if input_str.starts_with('-') {
+        // goes to the solver as an argument, a flag there could e.g. write a trace over any file
+        stream.write(b"ERROR: Invalid input").unwrap();
+        stream.flush().unwrap();
+        return;
+    }
+    if anytime {
+        stream_improvements(stream, input_str);
+        return;
+    }
+
+    let output = solver_command()
+        .arg("--")
         .arg(input_str)
         .output()
         .expect("failed to execute process");
 
     if !output.status.success() {
-        let response;
         println!("error! status: {}", output.status);
         println!("stdout: {}", String::from_utf8_lossy(&output.stdout));
         println!("stderr: {}", String::from_utf8_lossy(&output.stderr));
-        response = "ERROR".to_string();
-        stream.write(response.as_bytes()).unwrap();
+        stream.write(error_response(&output.stderr).as_bytes()).unwrap();
         stream.flush().unwrap();
         return;
     }
@@ -46,3 +91,41 @@ fn handle_connection(mut stream: TcpStream) {
     stream.write(output.stdout.as_slice()).unwrap();
     stream.flush().unwrap();
 }
+
+fn error_response(stderr: &[u8]) -> String {
+    match String::from_utf8_lossy(stderr).trim() {
+        "" => "ERROR".to_string(),
+        reason => format!("ERROR: {}", reason),
+    }
+}
+
+fn stream_improvements(mut stream: TcpStream, input_str: &str) {
+    let mut child = solver_command()
+        .arg("--anytime")
+        .arg("--")
+        .arg(input_str)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to execute process");
+
+    // each line is `<moves> <hp> <nodes>`, the client keeps the last one
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+    for line in stdout.lines() {
+        let line = line.unwrap();
+        if stream.write_all(format!("{}\n", line).as_bytes()).is_err() {
+            // client is gone, no point in finishing the search
+            child.kill().ok();
+            break;
+        }
+        stream.flush().ok();
+    }
+
+    let output = child.wait_with_output().expect("failed to wait on process");
+    if !output.status.success() && output.stdout.is_empty() {
+        println!("error! status: {}", output.status);
+        println!("stderr: {}", String::from_utf8_lossy(&output.stderr));
+        stream.write_all(error_response(&output.stderr).as_bytes()).ok();
+        stream.flush().ok();
+    }
+}
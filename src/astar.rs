@@ -0,0 +1,238 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use hash_hasher::HashBuildHasher;
+
+use crate::budget::BudgetTracker;
+use crate::game::{D15Game, Entity, Move, Point, StateKey};
+use crate::profiles::StrategyProfile;
+use crate::solver::{Solution, SolveObjective};
+use crate::stats::SolveStats;
+
+const PIECES: [Entity; 4] = [Entity::PLAYER, Entity::CAT, Entity::DOG, Entity::DRAGON];
+
+/// Admissible lower bound on the moves still needed before `game` is won, `None` if it can't be won.
+///
+/// Every pet can cross the room in a single turn (jumps, swaps, slides), so tile distances can't
+/// bound the number of turns left. What holds is that an unfinished board needs at least one more
+/// move, that it needs two if the player can't end the next turn next to the boss, and that a boss
+/// on the room edge can never be surrounded.
+pub fn moves_lower_bound(game: &D15Game) -> Option<u32> {
+    if game.is_boss_on_edge() {
+        return None;
+    }
+    if game.check_win_2() {
+        Some(0)
+    } else if player_reaches_boss_in_one(game) {
+        Some(1)
+    } else {
+        Some(2)
+    }
+}
+
+/// The player only moves on its own turn: it stays, steps along an axis or swaps onto a pet's tile.
+/// Blocking and room bounds are ignored, this only has to never miss a tile.
+fn player_reaches_boss_in_one(game: &D15Game) -> bool {
+    let player = game.get_entity_position(Entity::PLAYER);
+    let step = game.get_moveamount();
+    [
+        player.clone(),
+        Point { x: player.x + step, y: player.y },
+        Point { x: player.x - step, y: player.y },
+        Point { x: player.x, y: player.y + step },
+        Point { x: player.x, y: player.y - step },
+        game.get_entity_position(Entity::CAT).clone(),
+        game.get_entity_position(Entity::DOG).clone(),
+        game.get_entity_position(Entity::DRAGON).clone(),
+    ].iter().any(|tile| game.is_boss_adjacent(tile))
+}
+
+/// How far the board is from surrounding the boss: the number of empty boss-adjacent tiles first,
+/// then how far the closest piece is from each of them. Only breaks ties between equal bounds, so it
+/// doesn't have to be admissible.
+pub fn distance_to_win(game: &D15Game) -> u16 {
+    let mut empty = 0;
+    let mut distance = 0;
+    for tile in game.boss_adjacent().iter() {
+        let closest = PIECES.iter()
+            .map(|&piece| {
+                let at = game.get_entity_position(piece);
+                ((at.x - tile.x).abs() + (at.y - tile.y).abs()) as u16
+            })
+            .min()
+            .unwrap();
+        if closest > 0 {
+            empty += 1;
+            distance += closest;
+        }
+    }
+    empty * 16 + distance
+}
+
+struct Node {
+    game: D15Game,
+    cost: u64,
+    depth: usize,
+    parent: Option<(usize, Move)>,
+}
+
+fn moves_to(nodes: &[Node], mut index: usize) -> Vec<Move> {
+    let mut moves = vec![];
+    while let Some((parent, move_oper)) = nodes[index].parent {
+        moves.push(move_oper);
+        index = parent;
+    }
+    moves.reverse();
+    moves
+}
+
+/// Best-first search on the objective's cost. The first won board taken off the queue is the
+/// cheapest of all lines that stay above `besthp`.
+///
+/// A board reached again at no lower cost is dropped as soon as it's generated. For `MaxHp` that is
+/// every repeat, as the hp is part of the board and fixes the cost of reaching it.
+///
+/// Gives up with `None` once the budget runs out, there is no best-so-far before the first win.
+/// Repeats dropped count as transposition hits in `stats`.
+pub fn astar_search(game: &D15Game, besthp: i16, objective: SolveObjective, profile: &StrategyProfile, budget: &mut BudgetTracker, stats: &mut SolveStats) -> Option<Solution> {
+    moves_lower_bound(game)?;
+
+    let mut nodes = vec![Node { game: game.clone(), cost: 0, depth: 0, parent: None }];
+    let mut open = BinaryHeap::new();
+    let mut best_cost: HashMap<StateKey, u64, HashBuildHasher> = HashMap::with_capacity_and_hasher(1000000, HashBuildHasher::default());
+
+    best_cost.insert(game.key(), 0);
+    open.push(Reverse((0, distance_to_win(game), 0)));
+
+    let mut found = None;
+    while let Some(Reverse((_, _, index))) = open.pop() {
+        let current = &nodes[index];
+
+        if current.game.check_win_2() {
+            found = Some(Solution::new(game, moves_to(&nodes, index), current.cost));
+            break;
+        }
+        if best_cost[&current.game.key()] < current.cost {
+            stats.transposition_hits += 1;
+            continue;
+        }
+        if !budget.expand() {
+            break;
+        }
+        stats.max_depth = stats.max_depth.max(current.depth);
+
+        for move_oper in profile.moves(&current.game) {
+            let mut new_game = nodes[index].game.clone();
+            new_game.do_move(&move_oper);
+            if new_game.hp <= besthp {
+                stats.besthp_prunes += 1;
+                continue;
+            }
+            if new_game.is_dead() {
+                continue;
+            }
+            let bound = match moves_lower_bound(&new_game) {
+                Some(bound) => bound,
+                None => continue,
+            };
+            let cost = nodes[index].cost.saturating_add(objective.move_cost(move_oper, nodes[index].game.hp - new_game.hp));
+            let game_key = new_game.key();
+            match best_cost.get(&game_key) {
+                Some(&seen) if seen <= cost => {
+                    stats.transposition_hits += 1;
+                    continue;
+                },
+                _ => best_cost.insert(game_key, cost),
+            };
+
+            let key = (cost.saturating_add((bound as u64).saturating_mul(objective.min_move_cost(new_game.rules()))), distance_to_win(&new_game), nodes.len());
+            nodes.push(Node { game: new_game, cost, depth: nodes[index].depth + 1, parent: Some((index, move_oper)) });
+            open.push(Reverse(key));
+        }
+    }
+
+    stats.peak_table_size = stats.peak_table_size.max(best_cost.len());
+    found
+}
+
+/// Number of moves two lines differ in, position by position. Moves past the end of the shorter
+/// line all count as different.
+pub fn move_difference(a: &[Move], b: &[Move]) -> usize {
+    let changed = a.iter().zip(b).filter(|(x, y)| x != y).count();
+    changed + a.len().max(b.len()) - a.len().min(b.len())
+}
+
+/// The `k` cheapest lines above `besthp`, best first. Every accepted line differs from each line
+/// before it in at least `min_difference` moves.
+///
+/// Unlike `astar_search` a board can't be dropped the second time it shows up, another line through
+/// it may be one of the `k`. Each board is expanded at most `k` times instead, which covers every
+/// prefix the k-th best line can need. Lines turned down for being too similar use up expansions
+/// as well, so with a `min_difference` the later alternatives are the best ones left rather than
+/// provably the best diverse set.
+///
+/// Once the budget runs out the lines found so far are returned, they are still the best ones.
+/// Boards already expanded `k` times count as transposition hits in `stats`.
+#[allow(clippy::too_many_arguments)]
+pub fn astar_k_best(game: &D15Game, besthp: i16, objective: SolveObjective, profile: &StrategyProfile, k: usize, min_difference: usize, budget: &mut BudgetTracker, stats: &mut SolveStats) -> Vec<Solution> {
+    let mut solutions: Vec<Solution> = vec![];
+    if k == 0 || moves_lower_bound(game).is_none() {
+        return solutions;
+    }
+
+    let mut nodes = vec![Node { game: game.clone(), cost: 0, depth: 0, parent: None }];
+    let mut open = BinaryHeap::new();
+    let mut expanded: HashMap<StateKey, usize, HashBuildHasher> = HashMap::with_capacity_and_hasher(1000000, HashBuildHasher::default());
+
+    open.push(Reverse((0, distance_to_win(game), 0)));
+
+    while let Some(Reverse((_, _, index))) = open.pop() {
+        let current = &nodes[index];
+
+        if current.game.check_win_2() {
+            let moves = moves_to(&nodes, index);
+            if solutions.iter().all(|solution| move_difference(&solution.moves, &moves) >= min_difference) {
+                solutions.push(Solution::new(game, moves, current.cost));
+                if solutions.len() == k {
+                    break;
+                }
+            }
+            continue;
+        }
+
+        let expansions = expanded.entry(current.game.key()).or_insert(0);
+        if *expansions == k {
+            stats.transposition_hits += 1;
+            continue;
+        }
+        *expansions += 1;
+        if !budget.expand() {
+            break;
+        }
+        stats.max_depth = stats.max_depth.max(current.depth);
+
+        for move_oper in profile.moves(&current.game) {
+            let mut new_game = nodes[index].game.clone();
+            new_game.do_move(&move_oper);
+            if new_game.hp <= besthp {
+                stats.besthp_prunes += 1;
+                continue;
+            }
+            if new_game.is_dead() {
+                continue;
+            }
+            let bound = match moves_lower_bound(&new_game) {
+                Some(bound) => bound,
+                None => continue,
+            };
+
+            let cost = nodes[index].cost.saturating_add(objective.move_cost(move_oper, nodes[index].game.hp - new_game.hp));
+            let key = (cost.saturating_add((bound as u64).saturating_mul(objective.min_move_cost(new_game.rules()))), distance_to_win(&new_game), nodes.len());
+            nodes.push(Node { game: new_game, cost, depth: nodes[index].depth + 1, parent: Some((index, move_oper)) });
+            open.push(Reverse(key));
+        }
+    }
+
+    stats.peak_table_size = stats.peak_table_size.max(expanded.len());
+    solutions
+}
@@ -0,0 +1,51 @@
+use std::env;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::process;
+
+use ed15r::{generate_tablebase, Rules, DEFAULT_RULES};
+
+fn usage(program: &str) -> ! {
+    eprintln!("usage: {} [--min-hp=N] [--rules=FILE] <output file> <magic string>...", program);
+    process::exit(2);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = &args[0];
+
+    let mut min_hp = 0;
+    let mut rules = &DEFAULT_RULES;
+    let mut positional = vec![];
+    for arg in &args[1..] {
+        if let Some(value) = arg.strip_prefix("--min-hp=") {
+            min_hp = value.parse().unwrap_or_else(|_| usage(program));
+        } else if let Some(path) = arg.strip_prefix("--rules=") {
+            rules = Rules::open(Path::new(path)).unwrap_or_else(|e| {
+                eprint!("Could not load rules {} - {}", path, e);
+                process::exit(1);
+            }).leak();
+        } else if !arg.starts_with("--") {
+            positional.push(arg.as_str());
+        } else {
+            usage(program);
+        }
+    }
+    if positional.len() < 2 {
+        usage(program);
+    }
+
+    let tablebase = generate_tablebase(&positional[1..], min_hp, rules).unwrap_or_else(|e| {
+        eprint!("Invalid input - {}", e);
+        process::exit(1);
+    });
+
+    let written = File::create(positional[0]).and_then(|file| tablebase.write_to(&mut BufWriter::new(file)));
+    if let Err(e) = written {
+        eprint!("Could not write {} - {}", positional[0], e);
+        process::exit(1);
+    }
+    // what isn't reachable from the starts stays a search, say so
+    println!("{} boards reachable from {} starts above {} hp, other boards aren't covered", tablebase.len(), positional.len() - 1, tablebase.min_hp());
+}
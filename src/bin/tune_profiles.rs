@@ -0,0 +1,76 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use ed15r::game::Point;
+use ed15r::profiles::{tune, StrategyProfiles};
+use ed15r::{parse_magicstr_with_rules, Rules, SolveBudget, SolveOptions, DEFAULT_RULES};
+
+fn usage(program: &str) -> ! {
+    eprintln!("usage: {} [--base=FILE] [--max-nodes=N] [--threads=N] [--rules=FILE] <corpus file> <output file>", program);
+    eprintln!("the corpus has a magic string per line, empty lines and lines starting with # are skipped");
+    process::exit(2);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = &args[0];
+
+    let mut base = StrategyProfiles::default();
+    let mut options = SolveOptions::default();
+    let mut rules = &DEFAULT_RULES;
+    let mut positional = vec![];
+    for arg in &args[1..] {
+        if let Some(path) = arg.strip_prefix("--base=") {
+            base = StrategyProfiles::open(Path::new(path)).unwrap_or_else(|e| {
+                eprint!("Could not load profiles {} - {}", path, e);
+                process::exit(1);
+            });
+        } else if let Some(value) = arg.strip_prefix("--max-nodes=") {
+            options.budget = SolveBudget { max_nodes: Some(value.parse().unwrap_or_else(|_| usage(program))), ..SolveBudget::default() };
+        } else if let Some(value) = arg.strip_prefix("--threads=") {
+            options.threads = value.parse().unwrap_or_else(|_| usage(program));
+        } else if let Some(path) = arg.strip_prefix("--rules=") {
+            rules = Rules::open(Path::new(path)).unwrap_or_else(|e| {
+                eprint!("Could not load rules {} - {}", path, e);
+                process::exit(1);
+            }).leak();
+        } else if !arg.starts_with("--") {
+            positional.push(arg.as_str());
+        } else {
+            usage(program);
+        }
+    }
+    if positional.len() != 2 {
+        usage(program);
+    }
+    options.rules = Some(rules);
+
+    let corpus = fs::read_to_string(positional[0]).unwrap_or_else(|e| {
+        eprint!("Could not read {} - {}", positional[0], e);
+        process::exit(1);
+    });
+    let boards = corpus.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| parse_magicstr_with_rules(line, rules).map_err(|e| (line, e)))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap_or_else(|(line, e)| {
+            eprint!("Invalid input {} - {}", line, e);
+            process::exit(1);
+        });
+
+    let tuned = tune(&boards, &base, &options);
+    for position in &tuned.positions {
+        if base.get(&Point { x: position.x, y: position.y }) != &position.profile {
+            let profile = &position.profile;
+            println!("boss {},{}: besthp start-{}/{}/{}/{}, {:?}", position.x, position.y, profile.search_best_margin, profile.besthp_high, profile.besthp_mid, profile.besthp_low, profile.ordering);
+        }
+    }
+
+    if let Err(e) = fs::write(positional[1], tuned.to_json()) {
+        eprint!("Could not write {} - {}", positional[1], e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,184 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Deadline and cancellation are only looked at every this many nodes, the node limit on every one.
+const CHECK_INTERVAL: u64 = 1024;
+
+/// Elapsed time since `start()`. `std::time::Instant` panics on wasm32-unknown-unknown, so the
+/// browser clock stands in there.
+#[derive(Clone, Copy)]
+pub struct Stopwatch {
+    #[cfg(not(target_arch = "wasm32"))]
+    started: std::time::Instant,
+    #[cfg(target_arch = "wasm32")]
+    started: f64,
+}
+
+impl Stopwatch {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn start() -> Stopwatch {
+        Stopwatch { started: std::time::Instant::now() }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn start() -> Stopwatch {
+        Stopwatch { started: js_sys::Date::now() }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_secs_f64((js_sys::Date::now() - self.started).max(0.0) / 1000.0)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StopReason {
+    NodeLimit,
+    Deadline,
+    Cancelled,
+}
+
+/// Limits on a single solve. Whatever runs out first stops the search, which then keeps the best
+/// line it found so far.
+#[derive(Clone, Default)]
+pub struct SolveBudget {
+    /// Most boards the search may expand.
+    pub max_nodes: Option<u64>,
+    /// Longest the search may run, counted from the start of the solve.
+    pub time_limit: Option<Duration>,
+    /// Stops the search once set, e.g. from another thread.
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+/// Counts expanded boards against a `SolveBudget`.
+pub struct BudgetTracker {
+    budget: SolveBudget,
+    nodes: u64,
+    /// Nodes of all trackers forked off the same one, each adds its own every `CHECK_INTERVAL`.
+    shared_nodes: Option<Arc<AtomicU64>>,
+    stopwatch: Stopwatch,
+    stopped: Option<StopReason>,
+}
+
+impl BudgetTracker {
+    pub fn new(budget: SolveBudget) -> BudgetTracker {
+        BudgetTracker {
+            budget,
+            nodes: 0,
+            shared_nodes: None,
+            stopwatch: Stopwatch::start(),
+            stopped: None,
+        }
+    }
+
+    /// Trackers for `workers` threads that use up this one's budget together. They only sum up their
+    /// nodes every `CHECK_INTERVAL`, so the node limit can be overshot by that much per worker.
+    pub fn fork(&self, workers: usize) -> Vec<BudgetTracker> {
+        let shared_nodes = Arc::new(AtomicU64::new(self.nodes));
+        (0..workers).map(|_| BudgetTracker {
+            budget: self.budget.clone(),
+            nodes: 0,
+            shared_nodes: Some(shared_nodes.clone()),
+            stopwatch: self.stopwatch,
+            stopped: self.stopped,
+        }).collect()
+    }
+
+    /// Takes over the nodes and the stop reason of the trackers `fork` handed out.
+    pub fn join(&mut self, workers: Vec<BudgetTracker>) {
+        for worker in workers {
+            self.nodes += worker.nodes;
+            self.stopped = self.stopped.or(worker.stopped);
+        }
+    }
+
+    /// Counts one expanded board. False once the budget is used up, from then on for good.
+    pub fn expand(&mut self) -> bool {
+        if self.stopped.is_some() {
+            return false;
+        }
+        self.nodes += 1;
+        let checkpoint = self.nodes.is_multiple_of(CHECK_INTERVAL);
+        let total = match &self.shared_nodes {
+            None => Some(self.nodes),
+            Some(shared_nodes) if checkpoint => Some(shared_nodes.fetch_add(CHECK_INTERVAL, Ordering::Relaxed) + CHECK_INTERVAL),
+            Some(_) => None,
+        };
+
+        if self.budget.max_nodes.is_some_and(|max_nodes| total.is_some_and(|total| total > max_nodes)) {
+            self.stopped = Some(StopReason::NodeLimit);
+        } else if checkpoint {
+            if self.budget.cancel.as_ref().is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+                self.stopped = Some(StopReason::Cancelled);
+            } else if self.budget.time_limit.is_some_and(|time_limit| self.stopwatch.elapsed() >= time_limit) {
+                self.stopped = Some(StopReason::Deadline);
+            }
+        }
+        self.stopped.is_none()
+    }
+
+    /// Boards expanded so far, by all workers for a forked tracker.
+    pub fn nodes(&self) -> u64 {
+        match &self.shared_nodes {
+            Some(shared_nodes) => shared_nodes.load(Ordering::Relaxed) + self.nodes % CHECK_INTERVAL,
+            None => self.nodes,
+        }
+    }
+
+    /// Time since the tracker was created, the same for every forked one.
+    pub fn elapsed(&self) -> Duration {
+        self.stopwatch.elapsed()
+    }
+
+    pub fn stopped(&self) -> Option<StopReason> {
+        self.stopped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_past_the_node_limit_for_good() {
+        let mut tracker = BudgetTracker::new(SolveBudget { max_nodes: Some(3), ..SolveBudget::default() });
+        assert!((0..3).all(|_| tracker.expand()));
+        assert!(!tracker.expand());
+        assert!(!tracker.expand());
+        assert_eq!(tracker.stopped(), Some(StopReason::NodeLimit));
+        assert_eq!(tracker.nodes(), 4);
+    }
+
+    #[test]
+    fn looks_at_cancel_and_deadline_every_check_interval() {
+        let cancel = Arc::new(AtomicBool::new(true));
+        let mut cancelled = BudgetTracker::new(SolveBudget { cancel: Some(cancel), ..SolveBudget::default() });
+        assert!((1..CHECK_INTERVAL).all(|_| cancelled.expand()));
+        assert!(!cancelled.expand());
+        assert_eq!(cancelled.stopped(), Some(StopReason::Cancelled));
+
+        let mut late = BudgetTracker::new(SolveBudget { time_limit: Some(Duration::ZERO), ..SolveBudget::default() });
+        assert!((1..CHECK_INTERVAL).all(|_| late.expand()));
+        assert!(!late.expand());
+        assert_eq!(late.stopped(), Some(StopReason::Deadline));
+    }
+
+    #[test]
+    fn forked_trackers_share_the_node_limit() {
+        let mut tracker = BudgetTracker::new(SolveBudget { max_nodes: Some(3 * CHECK_INTERVAL), ..SolveBudget::default() });
+        let mut workers = tracker.fork(2);
+        let mut expanded = 0;
+        while workers.iter_mut().all(|worker| worker.expand()) {
+            expanded += 2;
+        }
+        assert!((2 * CHECK_INTERVAL..=4 * CHECK_INTERVAL).contains(&expanded), "{}", expanded);
+        tracker.join(workers);
+        assert_eq!(tracker.stopped(), Some(StopReason::NodeLimit));
+    }
+}
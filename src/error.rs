@@ -0,0 +1,237 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::budget::StopReason;
+use crate::game::Entity;
+use crate::stats::SolveStats;
+
+pub const MAGIC_FIELD_COUNT: usize = 13;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    FieldCount { found: usize },
+    InvalidNumber { index: usize, value: String },
+    /// Outside of the rules' `start_hp_min..=start_hp_max`.
+    HpOutOfRange { index: usize, hp: i16, min: i16, max: i16 },
+    /// Outside of the room, `0..=max` with `max` the `game::ROOM_MAX_X` or `ROOM_MAX_Y`.
+    CoordinateOutOfRange { index: usize, value: i8, max: i8 },
+    InvalidPlayerState { index: usize, value: String },
+    InvalidColor { index: usize, value: String },
+    /// In a `LEFT_UP_DOG_`-style move list, `index` counts the moves.
+    InvalidMove { index: usize, value: String },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::FieldCount { found } => write!(f, "expected {} fields separated by '_', got {}", MAGIC_FIELD_COUNT, found),
+            ParseError::InvalidNumber { index, value } => write!(f, "field {}: '{}' is not a number", index, value),
+            ParseError::HpOutOfRange { index, hp, min, max } => write!(f, "field {}: start hp {} must be within {}..={}", index, hp, min, max),
+            ParseError::CoordinateOutOfRange { index, value, max } => write!(f, "field {}: coordinate {} must be within 0..={}", index, value, max),
+            ParseError::InvalidPlayerState { index, value } => write!(f, "field {}: player state '{}' must be S or A", index, value),
+            ParseError::InvalidColor { index, value } => write!(f, "field {}: color '{}' must be Y, G, R or B", index, value),
+            ParseError::InvalidMove { index, value } => write!(f, "move {}: '{}' is not a move", index + 1, value),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SolveError {
+    Parse(ParseError),
+    /// With what the search did before it gave up, see `SolveError::stats`.
+    Unsolvable { stats: SolveStats },
+    /// The budget ran out before any line was found.
+    OutOfBudget { reason: StopReason, stats: SolveStats },
+}
+
+impl SolveError {
+    /// What the search counted until it failed, `None` if it never ran.
+    pub fn stats(&self) -> Option<&SolveStats> {
+        match self {
+            SolveError::Parse(_) => None,
+            SolveError::Unsolvable { stats } | SolveError::OutOfBudget { stats, .. } => Some(stats),
+        }
+    }
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolveError::Parse(e) => write!(f, "Invalid input - {}", e),
+            SolveError::Unsolvable { .. } => write!(f, "Could not solve - try again ~20 hp down"),
+            SolveError::OutOfBudget { reason: StopReason::NodeLimit, .. } => write!(f, "Could not solve within the node limit"),
+            SolveError::OutOfBudget { reason: StopReason::Deadline, .. } => write!(f, "Could not solve within the time limit"),
+            SolveError::OutOfBudget { reason: StopReason::Cancelled, .. } => write!(f, "Solve was cancelled"),
+        }
+    }
+}
+
+impl Error for SolveError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SolveError::Parse(e) => Some(e),
+            SolveError::Unsolvable { .. } | SolveError::OutOfBudget { .. } => None,
+        }
+    }
+}
+
+impl From<ParseError> for SolveError {
+    fn from(e: ParseError) -> SolveError {
+        SolveError::Parse(e)
+    }
+}
+
+/// Why a move isn't among `D15Game::legal_moves`, see `D15Game::try_do_move`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IllegalMove {
+    /// The player would step out of the room.
+    OutOfBounds,
+    /// The player would step onto another piece.
+    BlockedBy(Entity),
+    /// The player is at or below the board's death hp, the game is over.
+    PlayerDead,
+}
+
+impl fmt::Display for IllegalMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IllegalMove::OutOfBounds => write!(f, "the player would leave the room"),
+            IllegalMove::BlockedBy(entity) => write!(f, "the {:?} is in the way", entity),
+            IllegalMove::PlayerDead => write!(f, "the player is dead"),
+        }
+    }
+}
+
+impl Error for IllegalMove {}
+
+#[derive(Debug)]
+pub enum TablebaseError {
+    Io(io::Error),
+    /// Not a tablebase file at all.
+    BadMagic,
+    UnsupportedVersion { found: u16 },
+    /// Built for another `game::RULES_VERSION`, the pets behave differently since.
+    RulesMismatch,
+    /// Shorter than its header says.
+    Truncated,
+    ChecksumMismatch,
+    /// The header or index points outside the file.
+    Corrupt,
+}
+
+impl fmt::Display for TablebaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TablebaseError::Io(e) => write!(f, "{}", e),
+            TablebaseError::BadMagic => write!(f, "not a tablebase file"),
+            TablebaseError::UnsupportedVersion { found } => write!(f, "unsupported tablebase format version {}", found),
+            TablebaseError::RulesMismatch => write!(f, "tablebase was built for different rules, generate it again"),
+            TablebaseError::Truncated => write!(f, "tablebase file is truncated"),
+            TablebaseError::ChecksumMismatch => write!(f, "tablebase checksum mismatch, the file is corrupt"),
+            TablebaseError::Corrupt => write!(f, "tablebase header doesn't fit the file, the file is corrupt"),
+        }
+    }
+}
+
+impl Error for TablebaseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TablebaseError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TablebaseError {
+    fn from(e: io::Error) -> TablebaseError {
+        TablebaseError::Io(e)
+    }
+}
+
+#[derive(Debug)]
+pub enum RulesError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Toml(toml::de::Error),
+    /// Numbers the search can't work with.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for RulesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RulesError::Io(e) => write!(f, "{}", e),
+            RulesError::Json(e) => write!(f, "invalid rules JSON: {}", e),
+            RulesError::Toml(e) => write!(f, "invalid rules TOML: {}", e),
+            RulesError::Invalid(reason) => write!(f, "invalid rules: {}", reason),
+        }
+    }
+}
+
+impl Error for RulesError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RulesError::Io(e) => Some(e),
+            RulesError::Json(e) => Some(e),
+            RulesError::Toml(e) => Some(e),
+            RulesError::Invalid(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for RulesError {
+    fn from(e: io::Error) -> RulesError {
+        RulesError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for RulesError {
+    fn from(e: serde_json::Error) -> RulesError {
+        RulesError::Json(e)
+    }
+}
+
+impl From<toml::de::Error> for RulesError {
+    fn from(e: toml::de::Error) -> RulesError {
+        RulesError::Toml(e)
+    }
+}
+
+#[derive(Debug)]
+pub enum ProfilesError {
+    Io(io::Error),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for ProfilesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfilesError::Io(e) => write!(f, "{}", e),
+            ProfilesError::Json(e) => write!(f, "invalid profiles JSON: {}", e),
+        }
+    }
+}
+
+impl Error for ProfilesError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ProfilesError::Io(e) => Some(e),
+            ProfilesError::Json(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for ProfilesError {
+    fn from(e: io::Error) -> ProfilesError {
+        ProfilesError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ProfilesError {
+    fn from(e: serde_json::Error) -> ProfilesError {
+        ProfilesError::Json(e)
+    }
+}
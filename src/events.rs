@@ -0,0 +1,78 @@
+use std::fmt;
+
+use crate::game::{Color, Entity, Phase, PlayerState, Point};
+
+/// Something that happened during a move, see `D15Game::do_move_logged`. Pets have a rule for
+/// each phase, `rule` says which one moved them and how, see `PetSchedule`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GameEvent {
+    PlayerMoved { from: Point, to: Point },
+    /// The player switched between sword and armor.
+    Switched { to: PlayerState },
+    /// PASSTURN, the pets don't move.
+    PassedTurn,
+    /// A piece wanted to go to `target` but couldn't: another piece is there, or it's outside the
+    /// room if `by` is `None`.
+    Blocked { entity: Entity, target: Point, by: Option<Entity> },
+    Moved { entity: Entity, from: Point, to: Point, rule: String },
+    Swapped { first: Entity, second: Entity, rule: String },
+    /// A pet that didn't move, e.g. because everywhere its rule wanted to go was blocked.
+    Stayed { entity: Entity, rule: String },
+    Damaged { amount: i16, hp: i16 },
+    /// The hp crossed a phase limit, the pets follow other rules from the next move on.
+    PhaseChanged { from: Phase, to: Phase, hp: i16 },
+    ColorShifted { from: Color, to: Color },
+    Won,
+    Lost { hp: i16 },
+}
+
+struct Tile<'a>(&'a Point);
+
+impl fmt::Display for Tile<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({},{})", self.0.x, self.0.y)
+    }
+}
+
+impl fmt::Display for GameEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameEvent::PlayerMoved { from, to } => write!(f, "player moved {} -> {}", Tile(from), Tile(to)),
+            GameEvent::Switched { to } => write!(f, "player switched to {:?}", to),
+            GameEvent::PassedTurn => write!(f, "player passed the turn, the pets stay"),
+            GameEvent::Blocked { entity, target, by: Some(by) } => write!(f, "{:?} tried {} blocked by {:?}", entity, Tile(target), by),
+            GameEvent::Blocked { entity, target, by: None } => write!(f, "{:?} tried {} outside the room", entity, Tile(target)),
+            GameEvent::Moved { entity, from, to, rule } => write!(f, "{:?} moved {} -> {} ({})", entity, Tile(from), Tile(to), rule),
+            GameEvent::Swapped { first, second, rule } => write!(f, "{:?} swapped with {:?} ({})", first, second, rule),
+            GameEvent::Stayed { entity, rule } => write!(f, "{:?} stayed ({})", entity, rule),
+            GameEvent::Damaged { amount, hp } => write!(f, "lost {} hp, {} left", amount, hp),
+            GameEvent::PhaseChanged { from, to, hp } => write!(f, "phase changed {}->{} at hp {}", from, to, hp),
+            GameEvent::ColorShifted { from, to } => write!(f, "sequence shifted {:?}->{:?}", from, to),
+            GameEvent::Won => write!(f, "boss surrounded, won"),
+            GameEvent::Lost { hp } => write!(f, "player died at {} hp", hp),
+        }
+    }
+}
+
+/// Where `D15Game` sends its events. The search moves on `()`, which drops them, and doesn't pay
+/// for building them: anything more than constructing the event goes behind `ENABLED`.
+pub(crate) trait EventLog {
+    const ENABLED: bool;
+
+    fn record(&mut self, event: GameEvent);
+}
+
+impl EventLog for () {
+    const ENABLED: bool = false;
+
+    #[inline(always)]
+    fn record(&mut self, _event: GameEvent) {}
+}
+
+impl EventLog for Vec<GameEvent> {
+    const ENABLED: bool = true;
+
+    fn record(&mut self, event: GameEvent) {
+        self.push(event);
+    }
+}
@@ -1,50 +1,93 @@
-use std::hash::Hash;
+//! The board and the rules: which moves are legal on a board (`D15Game::legal_moves`), what a
+//! move does to it including the pets' answer (`D15Game::do_move`) and when it's won
+//! (`D15Game::check_win_2`).
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::mem::swap;
 
+use crate::error::IllegalMove;
+use crate::events::{EventLog, GameEvent};
+use crate::pets::{PetLog, PetSchedule, D15_PETS};
+use crate::rules::{Rules, DEFAULT_RULES};
 use crate::game::Entity::{BOSS, DOG, DRAGON, PLAYER};
 
 pub const ROOM_MAX_X: i8 = 7;
 pub const ROOM_MAX_Y: i8 = 7;
 
-const PATTERN_1_LIMIT: i16 = 130;
-const PATTERN_2_LIMIT: i16 = 100;
-const PATTERN_3_LIMIT: i16 = 60;
-
-#[derive(Hash, Eq)]
-pub struct Point {
-    pub(crate) x: i8,
-    pub(crate) y: i8,
-}
+/// Bump whenever the pets' rules change in code, anything precomputed for the old ones
+/// (tablebases) is refused then. Changed numbers are in `Rules`, tablebases check those as well.
+pub const RULES_VERSION: u16 = 1;
 
-impl Clone for Point {
-    fn clone(&self) -> Point {
-        Point { x: self.x, y: self.y }
-    }
-}
+/// Hp at or below which the player is dead, unless a board is given another one with
+/// `D15Game::with_death_hp`.
+pub const DEATH_HP: i16 = 0;
 
-impl PartialEq for Point {
-    fn eq(&self, other: &Point) -> bool {
-        self.x == other.x && self.y == other.y
-    }
+/// A tile of the room, 0..=`ROOM_MAX_X` from left to right and 0..=`ROOM_MAX_Y` from top to bottom.
+#[derive(Clone, Hash, PartialEq, Eq, Debug)]
+pub struct Point {
+    pub x: i8,
+    pub y: i8,
 }
 
 impl Point {
-    fn mirror_by(&self, reference_point: &Point) -> Point {
+    /// The tile on the other side of `reference_point`, as far from it.
+    pub fn mirror_by(&self, reference_point: &Point) -> Point {
         let xdiff = reference_point.x - self.x;
         let ydiff = reference_point.y - self.y;
-        return Point { x: self.x + (2 * xdiff), y: self.y + (2 * ydiff) };
+        Point { x: self.x + (2 * xdiff), y: self.y + (2 * ydiff) }
     }
 
-    fn mirror_by_roomcenter(&self) -> Point {
+    /// The tile on the other side of the middle of the room.
+    pub fn mirror_by_roomcenter(&self) -> Point {
         Point { x: -self.x + ROOM_MAX_X, y: -self.y + ROOM_MAX_Y }
     }
 
     fn serialize(&self) -> i8 {
-        return self.y * 8 + self.x;
+        self.y * 8 + self.x
+    }
+
+    fn deserialize(serialized: i8) -> Point {
+        Point { x: serialized % 8, y: serialized / 8 }
     }
 }
 
-#[derive(PartialEq, Copy, Clone, Eq, Hash)]
+const POSITION_BITS: u32 = 6;
+const HP_SHIFT: u32 = 5 * POSITION_BITS;
+const PLAYERSTATE_SHIFT: u32 = HP_SHIFT + 16;
+const COLOR_SHIFT: u32 = PLAYERSTATE_SHIFT + 1;
+
+/// A board packed into 49 bits: the five positions at 6 bits each (player, boss, cat, dog, dragon),
+/// then hp, player state and color. Two boards share a key only if they are equal, see
+/// `D15Game::key` and `D15Game::from_key`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct StateKey(u64);
+
+impl StateKey {
+    /// The bits as they are stored, e.g. in a tablebase file.
+    pub fn packed(&self) -> u64 {
+        self.0
+    }
+
+    /// The key with its bits spread over all 64, for tables that use the hash as is
+    /// (`hash_hasher::HashBuildHasher`) or split on its high bits.
+    pub fn mixed(&self) -> u64 {
+        // splitmix64 finalizer, a bijection so distinct keys stay distinct
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl Hash for StateKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.mixed());
+    }
+}
+
+/// Which pet pattern comes next, the `Y|G|R|B` field of a magic string.
+#[derive(PartialEq, Copy, Clone, Eq, Hash, Debug)]
 pub enum Color {
     YELLOW,
     GREEN,
@@ -60,13 +103,13 @@ enum DogMove {
     DOWN
 }
 
-#[derive(Copy, Clone, Hash, PartialEq, Eq)]
+#[derive(Copy, Clone, Hash, PartialEq, Eq, Debug)]
 pub enum PlayerState {
     SWORD,
     ARMOR,
 }
 
-#[derive(PartialEq, Copy, Clone)]
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
 pub enum Move {
     LEFT,
     RIGHT,
@@ -79,7 +122,7 @@ pub enum Move {
     PASSTURN,
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Entity {
     PLAYER,
     BOSS,
@@ -88,9 +131,66 @@ pub enum Entity {
     DRAGON,
 }
 
-const ENTITIES: [Entity; 5] = [PLAYER, BOSS, Entity::CAT, DOG, DRAGON];
+/// Every move, in the order `D15Game::legal_moves` lists them.
+const ALL_MOVES: [Move; 9] = [Move::LEFT, Move::RIGHT, Move::DOWN, Move::UP, Move::DOG, Move::CAT, Move::DRAGON, Move::SWITCH, Move::PASSTURN];
 
-#[derive(Eq, PartialEq, Hash)]
+/// Every piece on the board, in the order positions are listed in, e.g. in `solver::Step`.
+pub const ENTITIES: [Entity; 5] = [PLAYER, BOSS, Entity::CAT, DOG, DRAGON];
+
+/// Which rules the pets follow, set by the hp the move starts at. Each phase lasts while the hp is
+/// above its `Rules::phase_limit`, the last one until the player dies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Phase {
+    ONE,
+    TWO,
+    THREE,
+    FOUR,
+}
+
+impl Phase {
+    pub fn next(self) -> Option<Phase> {
+        match self {
+            Phase::ONE => Some(Phase::TWO),
+            Phase::TWO => Some(Phase::THREE),
+            Phase::THREE => Some(Phase::FOUR),
+            Phase::FOUR => None,
+        }
+    }
+
+    /// 1 to 4, the pattern numbers the pets' rules are described with.
+    pub fn number(self) -> u8 {
+        self as u8 + 1
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.number())
+    }
+}
+
+/// Whether a board is still being played, see `D15Game::status`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GameStatus {
+    InProgress,
+    /// The boss is surrounded and the player is alive.
+    Won,
+    /// The player is at or below the death hp. No move is possible anymore.
+    Lost,
+}
+
+/// What `D15Game::try_do_move` did.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MoveOutcome {
+    /// Hp the move cost.
+    pub damage: i16,
+    /// Whether the board surrounds the boss now.
+    pub won: bool,
+}
+
+/// A board: where the five pieces stand, the player's hp and state and the next pet pattern.
+/// `lib.rs` parses it from a magic string, see `FromStr for D15Game`.
+#[derive(Eq, PartialEq, Hash, Debug)]
 pub struct D15Game {
     pub hp: i16,
     player: Point,
@@ -99,7 +199,13 @@ pub struct D15Game {
     dog: Point,
     dragon: Point,
     pub playerstate: PlayerState,
-    sequence: Color
+    sequence: Color,
+    /// Not part of `key`, a search only ever sees boards with the same one.
+    death_hp: i16,
+    /// Not part of `key` either, for the same reason.
+    rules: &'static Rules,
+    /// Neither is this.
+    pets: &'static PetSchedule,
 }
 
 impl D15Game {
@@ -118,35 +224,42 @@ impl D15Game {
         }
     }
 
-    fn can_move_to(&self, target_point: &Point, entity: Entity) -> bool {
+    /// Puts `entity` on `to` as it is, for `PetBehaviour`s. Checking it can go there is on them.
+    pub fn set_entity_position(&mut self, entity: Entity, to: Point) {
+        match entity {
+            PLAYER => self.player = to,
+            BOSS => self.boss = to,
+            Entity::CAT => self.cat = to,
+            DOG => self.dog = to,
+            DRAGON => self.dragon = to,
+        }
+    }
+
+    pub fn swap_entities(&mut self, first: Entity, second: Entity) {
+        let at = self.get_entity_position(first).clone();
+        let other = self.get_entity_position(second).clone();
+        self.set_entity_position(first, other);
+        self.set_entity_position(second, at);
+    }
+
+    /// Whether `entity` could stand on `target_point`: inside the room and no other piece there.
+    pub fn can_move_to(&self, target_point: &Point, entity: Entity) -> bool {
         if target_point.x > ROOM_MAX_X || target_point.x < 0 {
             return false;
         }
         if target_point.y > ROOM_MAX_Y || target_point.y < 0 {
             return false;
         }
-        return !self.is_point_blocked_for(entity, target_point);
-    }
-
-    fn find_move_until(&self, for_entity: Entity, step_x: i8, step_y: i8, steps: u8) -> Point {
-        let start = self.get_entity_position(for_entity);
-        let mut ok = start.clone();
-        let mut x_carry = start.x;
-        let mut y_carry = start.y;
-        for _ in 0..steps {
-            x_carry += step_x;
-            y_carry += step_y;
-            let point_to_check = Point { x: x_carry, y: y_carry };
-            if self.can_move_to(&point_to_check, for_entity) {
-                ok = point_to_check;
-            } else {
-                break;
-            }
-        };
-        return ok;
+        !self.is_point_blocked_for(entity, target_point)
     }
 
-    pub fn check_win(&self) -> bool {
+    /// What keeps `entity` off `target_point` if `can_move_to` says no, `None` for outside the room.
+    pub(crate) fn blocker(&self, entity: Entity, target_point: &Point) -> Option<Entity> {
+        ENTITIES.iter().copied().filter(|&x| x != entity).find(|&eachentity| self.get_entity_position(eachentity) == target_point)
+    }
+
+    /// The four tiles around the boss that have to be occupied to win.
+    pub fn boss_adjacent(&self) -> [Point; 4] {
         [
             Point {
                 x: self.boss.x + 1,
@@ -164,83 +277,196 @@ impl D15Game {
                 x: self.boss.x,
                 y: self.boss.y - 1,
             }
-        ].iter().all(|required_point| ENTITIES.iter().any(|entity| self.get_entity_position(*entity) == required_point))
+        ]
+    }
+
+    pub fn is_boss_adjacent(&self, point: &Point) -> bool {
+        (point.x - self.boss.x).abs() + (point.y - self.boss.y).abs() == 1
+    }
+
+    /// A boss on the room edge has a neighbour tile outside the room, so the game can't be won.
+    pub fn is_boss_on_edge(&self) -> bool {
+        self.boss.x == 0 || self.boss.x == ROOM_MAX_X || self.boss.y == 0 || self.boss.y == ROOM_MAX_Y
+    }
+
+    #[allow(dead_code)]
+    pub fn check_win(&self) -> bool {
+        self.boss_adjacent().iter().all(|required_point| ENTITIES.iter().any(|entity| self.get_entity_position(*entity) == required_point))
     }
 
+    /// Whether the boss is surrounded. Never on a board the player is dead on, and never with the boss
+    /// on the room edge: the tiles are compared by `serialize`, where one step past the edge would be
+    /// a tile on the next or previous row.
     pub fn check_win_2(&self) -> bool {
+        if self.is_dead() || self.is_boss_on_edge() {
+            return false;
+        }
         let spots = [self.player.serialize(), self.cat.serialize(), self.dog.serialize(), self.dragon.serialize()];
 
         let boss = self.boss.serialize();
 
-        return spots.contains(&(boss + 1)) && spots.contains(&(boss - 1)) && spots.contains(&(boss + 8)) && spots.contains(&(boss - 8));
+        spots.contains(&(boss + 1)) && spots.contains(&(boss - 1)) && spots.contains(&(boss + 8)) && spots.contains(&(boss - 8))
     }
 
-    pub fn get_boss_x(&self) -> i8 {
-        self.boss.x
+    pub fn is_dead(&self) -> bool {
+        self.hp <= self.death_hp
     }
 
-    fn get_moveamount(&self) -> i8 {
-        match self.playerstate {
-            PlayerState::SWORD => 2,
-            PlayerState::ARMOR => 1,
+    pub fn status(&self) -> GameStatus {
+        if self.is_dead() {
+            GameStatus::Lost
+        } else if self.check_win_2() {
+            GameStatus::Won
+        } else {
+            GameStatus::InProgress
         }
     }
 
-    pub fn get_possible_moves(&self) -> Vec<Move> {
-        let mut moves: Vec<Move> = Vec::with_capacity(9);
+    pub fn death_hp(&self) -> i16 {
+        self.death_hp
+    }
 
-        let move_amount = self.get_moveamount();
+    /// The same board with the player dead at or below `death_hp` instead of `DEATH_HP`, e.g. to
+    /// keep a safety margin.
+    pub fn with_death_hp(mut self, death_hp: i16) -> D15Game {
+        self.death_hp = death_hp;
+        self
+    }
 
-        let is55 = self.boss.x == 5;
+    pub fn rules(&self) -> &'static Rules {
+        self.rules
+    }
 
-        if self.hp < 140 && (self.hp >= 120 || (!is55 && self.hp >= 80)) {
-            moves.push(Move::PASSTURN);
+    /// The same board played by other rules, e.g. ones loaded with `Rules::from_json`.
+    pub fn with_rules(mut self, rules: &'static Rules) -> D15Game {
+        self.rules = rules;
+        self
+    }
+
+    pub fn pets(&self) -> &'static PetSchedule {
+        self.pets
+    }
+
+    /// The same board with other pets, e.g. to model another boss room.
+    pub fn with_pets(mut self, pets: &'static PetSchedule) -> D15Game {
+        self.pets = pets;
+        self
+    }
+
+    pub fn get_boss_x(&self) -> i8 {
+        self.boss.x
+    }
+
+    /// Phase the pets answer the next move in.
+    pub fn phase(&self) -> Phase {
+        self.rules.phase(self.hp)
+    }
+
+    /// Hp to lose until the next phase starts, `None` in the last one.
+    pub fn hp_until_next_phase(&self) -> Option<i16> {
+        self.rules.phase_limit(self.phase()).map(|limit| self.hp - limit)
+    }
+
+    /// Moves until the next phase starts if every one costs what a move in the current
+    /// `PlayerState` does, `None` in the last one. A PASSTURN or SWITCH on the way changes that.
+    pub fn moves_until_next_phase(&self) -> Option<u32> {
+        let damage = self.move_damage();
+        self.hp_until_next_phase().map(|hp| ((hp + damage - 1) / damage) as u32)
+    }
+
+    /// Hp a move that isn't a PASSTURN costs in the current `PlayerState`.
+    pub fn move_damage(&self) -> i16 {
+        match self.playerstate {
+            PlayerState::SWORD => self.rules.sword_damage,
+            PlayerState::ARMOR => self.rules.armor_damage,
         }
+    }
 
-        let left_possible = self.can_move_to(&Point { x: self.player.x - move_amount, y: self.player.y }, Entity::PLAYER);
-        let right_possible = self.can_move_to(&Point { x: self.player.x + move_amount, y: self.player.y }, Entity::PLAYER);
-        let down_possible = self.can_move_to(&Point { x: self.player.x, y: self.player.y - move_amount }, Entity::PLAYER);
-        let up_possible = self.can_move_to(&Point { x: self.player.x, y: self.player.y + move_amount }, Entity::PLAYER);
+    pub fn get_moveamount(&self) -> i8 {
+        match self.playerstate {
+            PlayerState::SWORD => self.rules.sword_step,
+            PlayerState::ARMOR => self.rules.armor_step,
+        }
+    }
 
-        /*
-        let prio_up = self.boss.y - self.player.y > 0;
-        let prio_down = self.player.y - self.boss.y > 0;
-        let prio_right = self.boss.x - self.player.x > 0;
-        let prio_left = self.player.x - self.boss.x > 0;
-        */
+    /// Every move the rules allow: the steps that stay in the room and off the other pieces, the
+    /// swaps, SWITCH and PASSTURN. None once the player is dead. A won board still has moves, the search always makes at
+    /// least one even if the start is won already. Which to try first is up to the search, see
+    /// `solver::MoveOrdering`.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let mut moves: Vec<Move> = Vec::with_capacity(9);
+        moves.extend(ALL_MOVES.iter().copied().filter(|&move_oper| self.check_move(move_oper).is_ok()));
+        moves
+    }
 
-        if left_possible {
-            moves.push(Move::LEFT);
+    /// Tile LEFT, RIGHT, UP or DOWN take the player to, `None` for the other moves.
+    fn player_step_target(&self, move_oper: Move) -> Option<Point> {
+        let move_amount = self.get_moveamount();
+        match move_oper {
+            Move::LEFT => Some(Point { x: self.player.x - move_amount, y: self.player.y }),
+            Move::RIGHT => Some(Point { x: self.player.x + move_amount, y: self.player.y }),
+            Move::DOWN => Some(Point { x: self.player.x, y: self.player.y - move_amount }),
+            Move::UP => Some(Point { x: self.player.x, y: self.player.y + move_amount }),
+            _ => None,
         }
-        if right_possible {
-            moves.push(Move::RIGHT);
+    }
+
+    /// Why `legal_moves` doesn't have `move_oper` on this board, `Ok` if it does.
+    pub fn check_move(&self, move_oper: Move) -> Result<(), IllegalMove> {
+        if self.is_dead() {
+            return Err(IllegalMove::PlayerDead);
         }
-        if down_possible {
-            moves.push(Move::DOWN);
+        let target = match self.player_step_target(move_oper) {
+            Some(target) => target,
+            None => return Ok(()),
+        };
+        if self.can_move_to(&target, PLAYER) {
+            return Ok(());
         }
-        if up_possible {
-            moves.push(Move::UP);
+        match self.blocker(PLAYER, &target) {
+            Some(entity) => Err(IllegalMove::BlockedBy(entity)),
+            None => Err(IllegalMove::OutOfBounds),
         }
+    }
 
-        moves.push(Move::DOG);
-        moves.push(Move::CAT);
-        moves.push(Move::DRAGON);
-        moves.push(Move::SWITCH);
+    /// `do_move` for the `legal_moves`, any other one is refused and leaves the board as it is.
+    pub fn try_do_move(&mut self, move_oper: Move) -> Result<MoveOutcome, IllegalMove> {
+        self.check_move(move_oper)?;
+        let hp = self.hp;
+        self.do_move(&move_oper);
+        Ok(MoveOutcome { damage: hp - self.hp, won: self.check_win_2() })
+    }
 
-        if is55 && self.hp >= 80 && self.hp < 120 {
-            moves.push(Move::PASSTURN);
-        }
+    /// Plays any move as it is, even one `legal_moves` doesn't have, see `try_do_move`.
+    /// Only a board the player is alive on can be moved on.
+    pub fn do_move(&mut self, direction: &Move) {
+        self.apply_move(direction, &mut ());
+    }
 
-        return moves;
+    /// `do_move`, and what happened during it in order: the player's move, each pet's, the damage,
+    /// a pattern change, the next color and whether the game is over.
+    pub fn do_move_logged(&mut self, direction: &Move) -> Vec<GameEvent> {
+        let mut events = vec![];
+        self.apply_move(direction, &mut events);
+        events
     }
 
-    pub fn do_move(&mut self, direction: &Move) {
+    fn apply_move<L: EventLog>(&mut self, direction: &Move, log: &mut L) {
+        debug_assert!(!self.is_dead(), "move on a board the player is dead on");
+        let hp = self.hp;
+        let player = self.player.clone();
         match direction {
-            Move::SWITCH => match self.playerstate {
-                PlayerState::SWORD => self.playerstate = PlayerState::ARMOR,
-                PlayerState::ARMOR => self.playerstate = PlayerState::SWORD,
+            Move::SWITCH => {
+                match self.playerstate {
+                    PlayerState::SWORD => self.playerstate = PlayerState::ARMOR,
+                    PlayerState::ARMOR => self.playerstate = PlayerState::SWORD,
+                };
+                log.record(GameEvent::Switched { to: self.playerstate });
+            },
+            Move::PASSTURN => {
+                self.hp -= self.rules.passturn_damage;
+                log.record(GameEvent::PassedTurn);
             },
-            Move::PASSTURN => self.hp -= 25,
             Move::UP => self.player = Point { x: self.player.x, y: self.player.y + self.get_moveamount() },
             Move::DOWN => self.player = Point { x: self.player.x, y: self.player.y - self.get_moveamount() },
             Move::LEFT => self.player = Point { x: self.player.x - self.get_moveamount(), y: self.player.y },
@@ -249,19 +475,61 @@ impl D15Game {
             Move::CAT => swap(&mut self.player, &mut self.cat),
             Move::DRAGON => swap(&mut self.player, &mut self.dragon),
         };
+        if L::ENABLED {
+            match direction {
+                Move::UP | Move::DOWN | Move::LEFT | Move::RIGHT => log.record(GameEvent::PlayerMoved { from: player, to: self.player.clone() }),
+                Move::DOG => log.record(GameEvent::Swapped { first: PLAYER, second: DOG, rule: "the player's move".to_string() }),
+                Move::CAT => log.record(GameEvent::Swapped { first: PLAYER, second: Entity::CAT, rule: "the player's move".to_string() }),
+                Move::DRAGON => log.record(GameEvent::Swapped { first: PLAYER, second: DRAGON, rule: "the player's move".to_string() }),
+                Move::SWITCH | Move::PASSTURN => {},
+            }
+        }
 
         if *direction != Move::PASSTURN {
-            self.dog_move();
-            self.cat_move();
-            self.dragon_move();
+            if L::ENABLED {
+                let mut events = vec![];
+                self.pets_move_logged(&mut events);
+                events.into_iter().for_each(|event| log.record(event));
+            } else {
+                self.pets_move();
+            }
         }
 
-        self.hp -= match self.playerstate {
-            PlayerState::SWORD => 4,
-            PlayerState::ARMOR => 2,
-        };
+        self.hp -= self.move_damage();
+        if L::ENABLED {
+            log.record(GameEvent::Damaged { amount: hp - self.hp, hp: self.hp });
+            let phase_before = self.rules.phase(hp);
+            if phase_before != self.phase() {
+                log.record(GameEvent::PhaseChanged { from: phase_before, to: self.phase(), hp: self.hp });
+            }
+        }
+
+        self.shift_sequence(log);
 
-        self.shift_sequence();
+        if L::ENABLED {
+            match self.status() {
+                GameStatus::Won => log.record(GameEvent::Won),
+                GameStatus::Lost => log.record(GameEvent::Lost { hp: self.hp }),
+                GameStatus::InProgress => {},
+            }
+        }
+    }
+
+    /// Each pet of the schedule in turn, by what it does in the phase the move started in.
+    fn pets_move(&mut self) {
+        let phase = self.phase();
+        let pets = self.pets;
+        for turn in pets.turns {
+            turn.phases[phase as usize].act(self, turn.pet);
+        }
+    }
+
+    fn pets_move_logged(&mut self, events: &mut Vec<GameEvent>) {
+        let phase = self.phase();
+        let pets = self.pets;
+        for turn in pets.turns {
+            turn.phases[phase as usize].act_logged(self, turn.pet, &mut PetLog::new(events, phase));
+        }
     }
 
     fn color_at(&self, x: i8, y: i8) -> Color {
@@ -310,7 +578,8 @@ impl D15Game {
         }
     }
 
-    fn shift_sequence(&mut self) {
+    fn shift_sequence<L: EventLog>(&mut self, log: &mut L) {
+        let before = self.sequence;
         match self.sequence {
             Color::YELLOW => {
                 self.sequence = Color::GREEN;
@@ -325,10 +594,21 @@ impl D15Game {
                 self.sequence = Color::YELLOW;
             }
         };
+        log.record(GameEvent::ColorShifted { from: before, to: self.sequence });
+    }
+
+    /// Way a `ColorJump` from `at` goes, by the color of the floor there and the next pet pattern.
+    pub(crate) fn color_jump_direction(&self, at: &Point) -> (i8, i8) {
+        match self.get_intended_dog_move_direction(at) {
+            DogMove::UP => (0, 1),
+            DogMove::LEFT => (-1, 0),
+            DogMove::RIGHT => (1, 0),
+            DogMove::DOWN => (0, -1),
+        }
     }
 
-    fn get_intended_dog_move_direction(&self) -> DogMove {
-        let dog_at = self.color_at(self.dog.x, self.dog.y);
+    fn get_intended_dog_move_direction(&self, at: &Point) -> DogMove {
+        let dog_at = self.color_at(at.x, at.y);
         match self.sequence {
             Color::YELLOW => {
                 match dog_at {
@@ -365,100 +645,9 @@ impl D15Game {
         }
     }
 
-    fn dog_move(&mut self) {
-        if self.hp > PATTERN_1_LIMIT {
-            let intended_x_dir;
-            let intended_y_dir;
-            match self.get_intended_dog_move_direction() {
-                DogMove::UP => {
-                    intended_x_dir = 0i8;
-                    intended_y_dir = 1i8;
-                },
-                DogMove::LEFT => {
-                    intended_x_dir = -1i8;
-                    intended_y_dir = 0i8;
-                },
-                DogMove::RIGHT => {
-                    intended_x_dir = 1i8;
-                    intended_y_dir = 0i8;
-                },
-                DogMove::DOWN => {
-                    intended_x_dir = 0i8;
-                    intended_y_dir = -1i8;
-                }
-            };
-            let prio_1 = Point{x: self.dog.x + (5 * intended_x_dir), y: self.dog.y + (5 * intended_y_dir)};
-            if self.can_move_to(&prio_1, Entity::DOG) {
-                self.dog = prio_1;
-                return;
-            }
-            let prio_2 = Point{x: self.dog.x + (-2 * intended_x_dir), y: self.dog.y + (-2 * intended_y_dir)};
-            if self.can_move_to(&prio_2, Entity::DOG) {
-                self.dog = prio_2;
-            }
-        } else if self.hp > PATTERN_2_LIMIT {
-            swap(&mut self.dog, &mut self.cat);
-        } else if self.hp > PATTERN_3_LIMIT {
-            self.dog = self.find_move_until(Entity::DOG, 0, 1, 3);
-        } else {
-            swap(&mut self.dog, &mut self.dragon);
-        }
-    }
-    fn cat_move(&mut self) {
-        if self.hp > PATTERN_1_LIMIT {
-            // move 1 towards dragon, prefer Y
-            let diff_x = self.dragon.x - self.cat.x;
-            let diff_y = self.dragon.y - self.cat.y;
-            let cat_move: Point;
-            if !(diff_x.abs() > 1 || diff_y.abs() > 1) {
-                return;
-            }
-            if diff_x.abs() > diff_y.abs() {
-                cat_move = Point { x: self.cat.x + diff_x.signum(), y: self.cat.y };
-            } else {
-                cat_move = Point { x: self.cat.x, y: self.cat.y + diff_y.signum() };
-            }
-            if self.can_move_to(&cat_move, Entity::CAT) {
-                self.cat = cat_move;
-            }
-        } else if self.hp > PATTERN_2_LIMIT {
-            self.cat = self.find_move_until(Entity::CAT, 0, -1, 3);
-        } else if self.hp > PATTERN_3_LIMIT {
-            // move 1 away from player, prefer y
-
-            let diff_x = self.cat.x - self.player.x;
-            let diff_y = self.cat.y - self.player.y;
-            let cat_move: Point;
-            if diff_x.abs() > diff_y.abs() {
-                cat_move = Point { x: self.cat.x + diff_x.signum(), y: self.cat.y };
-            } else {
-                cat_move = Point { x: self.cat.x, y: self.cat.y + diff_y.signum() };
-            }
-            if self.can_move_to(&cat_move, Entity::CAT) {
-                self.cat = cat_move;
-            }
-        } else {
-            self.cat = self.find_move_until(Entity::CAT, 1, 0, 3);
-        }
-    }
-    fn dragon_move(&mut self) {
-        if self.hp > PATTERN_1_LIMIT {
-            let mirror = self.dragon.mirror_by(&self.player);
-            if self.can_move_to(&mirror, Entity::DRAGON) {
-                self.dragon = mirror;
-            }
-        } else if self.hp > PATTERN_2_LIMIT {
-            // nothing
-        } else if self.hp > PATTERN_3_LIMIT {
-            let mirror = self.dragon.mirror_by_roomcenter();
-            if self.can_move_to(&mirror, Entity::DRAGON) {
-                self.dragon = mirror;
-            }
-        } else {
-            self.dragon = self.find_move_until(Entity::DRAGON, -1, 0, 3);
-        }
-    }
-
+    /// Doesn't check the board, every point has to be inside the room and the hp above 0. Parse a
+    /// magic string to get that checked.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(hp: i16, boss: Point, player: Point, cat: Point, dog: Point, dragon: Point, playerstate: PlayerState, sequence: Color) -> D15Game {
         D15Game {
             hp,
@@ -468,7 +657,62 @@ impl D15Game {
             dog,
             dragon,
             playerstate,
-            sequence
+            sequence,
+            death_hp: DEATH_HP,
+            rules: &DEFAULT_RULES,
+            pets: &D15_PETS,
+        }
+    }
+
+    /// The next pet pattern.
+    pub fn color(&self) -> Color {
+        self.sequence
+    }
+
+    pub(crate) fn key(&self) -> StateKey {
+        let positions = [&self.player, &self.boss, &self.cat, &self.dog, &self.dragon].iter()
+            .enumerate()
+            .fold(0, |key, (index, point)| key | (point.serialize() as u64) << (index as u32 * POSITION_BITS));
+        let playerstate = match self.playerstate {
+            PlayerState::SWORD => 0,
+            PlayerState::ARMOR => 1,
+        };
+        let color = match self.sequence {
+            Color::YELLOW => 0,
+            Color::GREEN => 1,
+            Color::RED => 2,
+            Color::BLUE => 3,
+        };
+        let key = StateKey(positions | (self.hp as u16 as u64) << HP_SHIFT | playerstate << PLAYERSTATE_SHIFT | color << COLOR_SHIFT);
+        debug_assert!(D15Game::from_key(key, self.rules).with_death_hp(self.death_hp).with_pets(self.pets) == *self, "state key doesn't round-trip");
+        key
+    }
+
+    /// The board has `DEATH_HP` and the D15 pets, the key doesn't know others, nor the rules.
+    pub(crate) fn from_key(key: StateKey, rules: &'static Rules) -> D15Game {
+        let position = |index: u32| Point::deserialize((key.0 >> (index * POSITION_BITS) & 0x3f) as i8);
+        let playerstate = match key.0 >> PLAYERSTATE_SHIFT & 1 {
+            0 => PlayerState::SWORD,
+            _ => PlayerState::ARMOR,
+        };
+        let sequence = match key.0 >> COLOR_SHIFT & 3 {
+            0 => Color::YELLOW,
+            1 => Color::GREEN,
+            2 => Color::RED,
+            _ => Color::BLUE,
+        };
+        D15Game {
+            hp: (key.0 >> HP_SHIFT) as u16 as i16,
+            player: position(0),
+            boss: position(1),
+            cat: position(2),
+            dog: position(3),
+            dragon: position(4),
+            playerstate,
+            sequence,
+            death_hp: DEATH_HP,
+            rules,
+            pets: &D15_PETS,
         }
     }
 }
@@ -483,7 +727,98 @@ impl Clone for D15Game {
             dog: self.dog.clone(),
             dragon: self.dragon.clone(),
             playerstate: self.playerstate,
-            sequence: self.sequence
+            sequence: self.sequence,
+            death_hp: self.death_hp,
+            rules: self.rules,
+            pets: self.pets,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(magic_string: &str) -> D15Game {
+        magic_string.parse().unwrap()
+    }
+
+    #[test]
+    fn state_key_round_trips() {
+        for magic_string in ["60_4_3_4_4_5_3_3_3_5_4_A_Y", "150_4_3_4_4_5_3_3_3_5_4_S_Y", "200_0_0_7_7_1_0_0_1_6_7_S_B", "50_7_7_0_0_6_7_7_6_5_5_A_R"] {
+            let game = game(magic_string);
+            let key = game.key();
+            assert_eq!(D15Game::from_key(key, game.rules()), game, "{}", magic_string);
+            assert_eq!(D15Game::from_key(key, game.rules()).key().packed(), key.packed());
+        }
+    }
+
+    #[test]
+    fn state_keys_tell_boards_apart() {
+        let start = game("60_4_3_4_4_5_3_3_3_5_4_A_Y");
+        let others = ["58_4_3_4_4_5_3_3_3_5_4_A_Y", "60_4_3_4_5_5_3_3_3_5_4_A_Y", "60_4_3_4_4_5_3_3_3_5_4_S_Y", "60_4_3_4_4_5_3_3_3_5_4_A_G"];
+        for other in others {
+            assert!(start.key() != game(other).key(), "{}", other);
+        }
+    }
+
+    #[test]
+    fn try_do_move_says_why_it_refuses() {
+        let mut boxed_in = game("60_4_3_4_4_5_3_3_3_5_4_A_Y");
+        assert_eq!(boxed_in.try_do_move(Move::DOWN), Err(IllegalMove::BlockedBy(Entity::BOSS)));
+        assert_eq!(boxed_in, game("60_4_3_4_4_5_3_3_3_5_4_A_Y"), "a refused move leaves the board as it is");
+
+        let mut at_the_edge = game("60_4_3_0_4_5_3_3_3_5_4_A_Y");
+        assert_eq!(at_the_edge.try_do_move(Move::LEFT), Err(IllegalMove::OutOfBounds));
+
+        let mut dead = game("60_4_3_4_4_5_3_3_3_5_4_A_Y").with_death_hp(60);
+        assert_eq!(dead.try_do_move(Move::UP), Err(IllegalMove::PlayerDead));
+        assert!(dead.legal_moves().is_empty());
+
+        let mut fine = game("60_4_3_4_4_5_3_3_3_5_4_A_Y");
+        assert_eq!(fine.try_do_move(Move::UP), Ok(MoveOutcome { damage: 2, won: false }));
+    }
+
+    #[test]
+    fn logged_moves_tell_what_happened_in_order() {
+        let start = game("61_4_3_4_4_5_3_3_3_5_4_A_Y").with_death_hp(59);
+        let mut logged = start.clone();
+        let events = logged.do_move_logged(&Move::UP);
+        let mut played = start.clone();
+        played.do_move(&Move::UP);
+        assert_eq!(logged, played);
+
+        assert_eq!(events[0], GameEvent::PlayerMoved { from: Point { x: 4, y: 4 }, to: Point { x: 4, y: 5 } });
+        assert_eq!(events.iter().filter(|event| matches!(event, GameEvent::Moved { .. })).count(), 3, "{:?}", events);
+        assert_eq!(events[events.len() - 4..], [
+            GameEvent::Damaged { amount: 2, hp: 59 },
+            GameEvent::PhaseChanged { from: Phase::THREE, to: Phase::FOUR, hp: 59 },
+            GameEvent::ColorShifted { from: Color::YELLOW, to: Color::GREEN },
+            GameEvent::Lost { hp: 59 },
+        ]);
+    }
+
+    #[test]
+    fn moves_until_next_phase_rounds_up_to_whole_moves() {
+        let cases = [("149_4_3_4_4_5_3_3_3_5_4_S_Y", Some(5)), ("101_4_3_4_4_5_3_3_3_5_4_A_Y", Some(1)), ("100_4_3_4_4_5_3_3_3_5_4_A_Y", Some(20)), ("59_4_3_4_4_5_3_3_3_5_4_A_Y", None)];
+        for (magic_string, moves) in cases {
+            assert_eq!(game(magic_string).moves_until_next_phase(), moves, "{}", magic_string);
         }
+        assert_eq!(game("149_4_3_4_4_5_3_3_3_5_4_S_Y").hp_until_next_phase(), Some(19));
+    }
+
+    #[test]
+    fn passturn_is_legal_at_any_hp() {
+        for hp in [60, 100, 150, 200] {
+            let mut game = game(&format!("{}_4_3_4_4_5_3_3_3_5_4_S_Y", hp));
+            assert!(game.legal_moves().contains(&Move::PASSTURN));
+            assert!(game.try_do_move(Move::PASSTURN).is_ok(), "{} hp", hp);
+        }
+    }
+
+    #[test]
+    fn legal_moves_leave_out_steps_off_the_room_and_onto_pieces() {
+        assert_eq!(game("60_4_3_0_4_5_3_3_3_5_4_A_Y").legal_moves(), [Move::RIGHT, Move::DOWN, Move::UP, Move::DOG, Move::CAT, Move::DRAGON, Move::SWITCH, Move::PASSTURN]);
+        assert_eq!(game("60_4_3_4_4_5_3_3_3_5_4_A_Y").legal_moves(), [Move::LEFT, Move::UP, Move::DOG, Move::CAT, Move::DRAGON, Move::SWITCH, Move::PASSTURN]);
     }
 }
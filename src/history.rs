@@ -0,0 +1,180 @@
+use crate::error::IllegalMove;
+use crate::game::{D15Game, Move, MoveOutcome};
+use crate::verify::IllegalStep;
+
+/// A game played move by move that can be taken back and replayed, e.g. to try another move at
+/// step 5 of a line.
+///
+/// Keeps the board before and after each move played, so undo, redo and jumping around never
+/// replay anything. Playing a move after an undo drops the moves that were undone, like in an
+/// editor.
+#[derive(Clone, Debug)]
+pub struct GameHistory {
+    /// `boards[i]` is the board after `moves[..i]`, `boards[0]` the start.
+    boards: Vec<D15Game>,
+    moves: Vec<Move>,
+    /// Moves of `moves` currently played, the rest can be redone.
+    step: usize,
+}
+
+impl GameHistory {
+    pub fn new(start: D15Game) -> GameHistory {
+        GameHistory { boards: vec![start], moves: vec![], step: 0 }
+    }
+
+    /// A history with all of `moves` played, at its last step.
+    pub fn replay(start: D15Game, moves: &[Move]) -> Result<GameHistory, IllegalStep> {
+        let mut history = GameHistory::new(start);
+        for (index, &move_oper) in moves.iter().enumerate() {
+            history.play(move_oper).map_err(|reason| IllegalStep { index, move_oper, reason })?;
+        }
+        Ok(history)
+    }
+
+    /// The board at the current step.
+    pub fn current(&self) -> &D15Game {
+        &self.boards[self.step]
+    }
+
+    pub fn start(&self) -> &D15Game {
+        &self.boards[0]
+    }
+
+    /// Moves played to get to the current step.
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    /// Moves recorded, including the ones undone.
+    pub fn len(&self) -> usize {
+        self.moves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty()
+    }
+
+    /// Moves recorded, including the ones undone. `moves()[..step()]` got the game to `current()`.
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    /// Each recorded move with the board before it.
+    pub fn entries(&self) -> impl Iterator<Item = (&D15Game, Move)> {
+        self.boards.iter().zip(self.moves.iter().copied())
+    }
+
+    /// The board after `step` moves, `None` past the recorded ones.
+    pub fn board_at(&self, step: usize) -> Option<&D15Game> {
+        self.boards.get(step)
+    }
+
+    /// Plays `move_oper` on the current board, see `D15Game::try_do_move`. Any undone moves are
+    /// dropped, unless it's the next one of them anyway.
+    pub fn play(&mut self, move_oper: Move) -> Result<MoveOutcome, IllegalMove> {
+        if self.moves.get(self.step) == Some(&move_oper) {
+            let outcome = MoveOutcome { damage: self.current().hp - self.boards[self.step + 1].hp, won: self.boards[self.step + 1].check_win_2() };
+            self.step += 1;
+            return Ok(outcome);
+        }
+
+        let mut next = self.current().clone();
+        let outcome = next.try_do_move(move_oper)?;
+        self.boards.truncate(self.step + 1);
+        self.moves.truncate(self.step);
+        self.boards.push(next);
+        self.moves.push(move_oper);
+        self.step += 1;
+        Ok(outcome)
+    }
+
+    /// Takes back the last move played, `None` at the start.
+    pub fn undo(&mut self) -> Option<Move> {
+        if self.step == 0 {
+            return None;
+        }
+        self.step -= 1;
+        Some(self.moves[self.step])
+    }
+
+    /// Plays the next undone move again, `None` if there is none.
+    pub fn redo(&mut self) -> Option<Move> {
+        let move_oper = *self.moves.get(self.step)?;
+        self.step += 1;
+        Some(move_oper)
+    }
+
+    /// Goes to the board after `step` of the recorded moves, false past the last one.
+    pub fn jump_to(&mut self, step: usize) -> bool {
+        if step > self.moves.len() {
+            return false;
+        }
+        self.step = step;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start() -> D15Game {
+        "60_4_3_4_4_5_3_3_3_5_4_A_Y".parse().unwrap()
+    }
+
+    #[test]
+    fn undo_and_redo_walk_the_recorded_moves() {
+        let mut history = GameHistory::replay(start(), &[Move::UP, Move::UP, Move::LEFT]).unwrap();
+        let end = history.current().clone();
+        assert_eq!(history.step(), 3);
+
+        assert_eq!(history.undo(), Some(Move::LEFT));
+        assert_eq!(history.undo(), Some(Move::UP));
+        assert_eq!(history.step(), 1);
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.redo(), Some(Move::UP));
+        assert_eq!(history.redo(), Some(Move::LEFT));
+        assert_eq!(history.redo(), None);
+        assert_eq!(*history.current(), end);
+
+        assert_eq!(history.undo(), Some(Move::LEFT));
+        assert_eq!(history.undo(), Some(Move::UP));
+        assert_eq!(history.undo(), Some(Move::UP));
+        assert_eq!(history.undo(), None);
+        assert_eq!(history.current(), history.start());
+    }
+
+    #[test]
+    fn playing_after_an_undo_drops_the_undone_moves() {
+        let mut history = GameHistory::replay(start(), &[Move::UP, Move::UP, Move::LEFT]).unwrap();
+        history.undo();
+        history.undo();
+        history.play(Move::UP).unwrap();
+        assert_eq!(history.len(), 3, "the next undone move keeps the rest");
+
+        history.play(Move::RIGHT).unwrap();
+        assert_eq!(history.moves(), [Move::UP, Move::UP, Move::RIGHT]);
+        assert_eq!(history.redo(), None);
+    }
+
+    #[test]
+    fn jump_to_goes_to_any_recorded_step() {
+        let mut history = GameHistory::replay(start(), &[Move::UP, Move::UP, Move::LEFT]).unwrap();
+        let mut replayed = start();
+        replayed.do_move(&Move::UP);
+
+        assert!(history.jump_to(1));
+        assert_eq!(*history.current(), replayed);
+        assert_eq!(history.board_at(1), Some(&replayed));
+        assert!(!history.jump_to(4));
+        assert_eq!(history.step(), 1);
+        assert!(history.jump_to(0));
+        assert_eq!(history.current(), history.start());
+    }
+
+    #[test]
+    fn replay_says_which_move_is_illegal() {
+        let error = GameHistory::replay(start(), &[Move::UP, Move::DOWN, Move::DOWN]).unwrap_err();
+        assert_eq!((error.index, error.move_oper), (2, Move::DOWN));
+    }
+}
@@ -1,164 +1,427 @@
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
+//! Solver for the D15 boss room: given a board, the moves that surround the boss with the most
+//! hp left.
+//!
+//! The string functions (`solve_magicstr*`, and the `solve_d15_*` ones for wasm) take a board as
+//! magic string and return the moves as text. To work with boards and lines directly, parse a
+//! `game::D15Game` and solve it with a `Solver` from `Solver::builder()`.
 
-use hash_hasher::{HashBuildHasher, HashedSet};
+#![allow(clippy::upper_case_acronyms)]
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
 use wasm_bindgen::prelude::*;
 
-use crate::game::{D15Game, Move, PlayerState, Point, Color};
+use crate::error::MAGIC_FIELD_COUNT;
+use crate::game::{D15Game, Entity, Move, PlayerState, Point, Color, ROOM_MAX_X, ROOM_MAX_Y};
+use crate::profiles::StrategyProfiles;
+
+pub use crate::budget::{SolveBudget, StopReason};
+pub use crate::error::{IllegalMove, ParseError, ProfilesError, RulesError, SolveError, TablebaseError};
+pub use crate::events::GameEvent;
+pub use crate::history::GameHistory;
+pub use crate::rules::{Rules, DEFAULT_RULES};
+pub use crate::solver::{Improvement, MoveOrdering, SearchStrategy, Solution, SolveMode, SolveObjective, SolveOptions, Solver, SolverBuilder, Step};
+pub use crate::stats::{SearchTrace, SolveStats};
+pub use crate::tablebase::Tablebase;
+pub use crate::verify::{verify, IllegalStep, Verification};
 
-mod game;
+mod astar;
+mod budget;
+mod error;
+mod events;
+pub mod game;
+mod history;
+mod parallel;
+pub mod pets;
+pub mod profiles;
+mod rules;
+mod solver;
+mod stats;
+mod tablebase;
+mod verify;
 
-fn calculate_hash<T: Hash>(t: &T) -> u64 {
-    let mut s = DefaultHasher::new();
-    t.hash(&mut s);
-    s.finish()
+fn ask_playerstate(input: &str, index: usize) -> Result<PlayerState, ParseError> {
+    match input {
+        "S" => Ok(PlayerState::SWORD),
+        "A" => Ok(PlayerState::ARMOR),
+        _ => Err(ParseError::InvalidPlayerState { index, value: input.to_string() })
+    }
+}
+fn ask_color(input: &str, index: usize) -> Result<Color, ParseError> { // TODO: YELLOW if 200 and blocked / or any <130
+    match input {
+        "Y" => Ok(Color::YELLOW),
+        "G" => Ok(Color::GREEN),
+        "R" => Ok(Color::RED),
+        "B" => Ok(Color::BLUE),
+        _ => Err(ParseError::InvalidColor { index, value: input.to_string() })
+    }
 }
 
-fn ask_playerstate(input: &String) -> PlayerState {
-    match input.as_str() {
-        "S" => PlayerState::SWORD,
-        "A" => PlayerState::ARMOR,
-        _ => panic!("PlayerState must be S or A")
+fn ask_move(input: &str, index: usize) -> Result<Move, ParseError> {
+    match input.to_ascii_uppercase().as_str() {
+        "PASSTURN" => Ok(Move::PASSTURN),
+        "LEFT" => Ok(Move::LEFT),
+        "RIGHT" => Ok(Move::RIGHT),
+        "UP" => Ok(Move::UP),
+        "DOWN" => Ok(Move::DOWN),
+        "DOG" => Ok(Move::DOG),
+        "CAT" => Ok(Move::CAT),
+        "DRAGON" => Ok(Move::DRAGON),
+        "SWITCH" => Ok(Move::SWITCH),
+        _ => Err(ParseError::InvalidMove { index, value: input.to_string() }),
     }
 }
-fn ask_color(input: &String) -> Color { // TODO: YELLOW if 200 and blocked / or any <130
-    match input.as_str() {
-        "Y" => Color::YELLOW,
-        "G" => Color::GREEN,
-        "R" => Color::RED,
-        "B" => Color::BLUE,
-        _ => panic!("Invalid color")
+
+fn ask_number<T: FromStr>(fields: &[&str], index: usize) -> Result<T, ParseError> {
+    fields[index].parse().map_err(|_| ParseError::InvalidNumber { index, value: fields[index].to_string() })
+}
+
+fn ask_coordinate(fields: &[&str], index: usize, max: i8) -> Result<i8, ParseError> {
+    let value = ask_number(fields, index)?;
+    if !(0..=max).contains(&value) {
+        return Err(ParseError::CoordinateOutOfRange { index, value, max });
     }
+    Ok(value)
 }
 
-#[wasm_bindgen]
-pub fn solve_d15_from_magicstr(magic_string : String) -> String {
-    let split = magic_string.split("_");
-    let res: Vec<String> = split.map(|s| s.to_string()).collect();
+fn ask_point(fields: &[&str], index: usize) -> Result<Point, ParseError> {
+    Ok(Point { x: ask_coordinate(fields, index, ROOM_MAX_X)?, y: ask_coordinate(fields, index + 1, ROOM_MAX_Y)? })
+}
 
-    if res.len() != 13 {
-        panic!("Invalid input")
+/// Parses a magic string into a board played by `rules`, the start hp has to be within their range.
+pub fn parse_magicstr_with_rules(magic_string: &str, rules: &'static Rules) -> Result<D15Game, ParseError> {
+    let fields: Vec<&str> = magic_string.trim().split('_').collect();
+
+    if fields.len() != MAGIC_FIELD_COUNT {
+        return Err(ParseError::FieldCount { found: fields.len() });
     }
 
-    let hp : i16 = res[0].parse().unwrap();
-    if hp > 200 || hp < 50 {
-        panic!("Invalid start hp")
+    let hp: i16 = ask_number(&fields, 0)?;
+    if !(rules.start_hp_min..=rules.start_hp_max).contains(&hp) {
+        return Err(ParseError::HpOutOfRange { index: 0, hp, min: rules.start_hp_min, max: rules.start_hp_max });
     }
-    let players_state = ask_playerstate(&res[11]);
-    let color = ask_color(&res[12]);
+    let players_state = ask_playerstate(fields[11], 11)?;
+    let color = ask_color(fields[12], 12)?;
 
-     let game = D15Game::new(
-         hp,
-         Point { x: res[1].parse().unwrap(), y: res[2].parse().unwrap() },
-         Point { x: res[3].parse().unwrap(), y: res[4].parse().unwrap() },
-         Point { x: res[5].parse().unwrap(), y: res[6].parse().unwrap() },
-         Point { x: res[7].parse().unwrap(), y: res[8].parse().unwrap() },
-         Point { x: res[9].parse().unwrap(), y: res[10].parse().unwrap() },
-         players_state,
-         color
-     );
+    Ok(D15Game::new(
+        hp,
+        ask_point(&fields, 1)?,
+        ask_point(&fields, 3)?,
+        ask_point(&fields, 5)?,
+        ask_point(&fields, 7)?,
+        ask_point(&fields, 9)?,
+        players_state,
+        color
+    ).with_rules(rules))
+}
 
-    let mut solver = Solver::new();
-    solver.do_solve(&game);
+fn parse_magicstr(magic_string: &str) -> Result<D15Game, ParseError> {
+    parse_magicstr_with_rules(magic_string, &DEFAULT_RULES)
+}
+
+/// Parses a magic string with the default rules, see `solve_magicstr`.
+impl FromStr for D15Game {
+    type Err = ParseError;
 
-    if solver.solve.is_none() {
-        return "Could not solve - try again ~20 hp down".to_string();
+    fn from_str(magic_string: &str) -> Result<D15Game, ParseError> {
+        parse_magicstr(magic_string)
     }
-    return print_result_moves(&solver.solve.unwrap());
 }
 
-struct Solver {
-    besthp: i16,
-    solve: Option<Vec<Move>>,
-    checked_perms: HashedSet<u64>,
-    search_best: bool,
-}
-impl Solver {
-    pub fn new() -> Solver {
-        Solver {
-            solve: None,
-            besthp: 0,
-            checked_perms: HashedSet::with_capacity_and_hasher(1000000, HashBuildHasher::default()),
-            search_best: false
+/// Writes the board as magic string, what `FromStr` reads. Boards outside the start hp range of
+/// their rules come out fine but won't parse again. The rules aren't part of it.
+impl fmt::Display for D15Game {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.hp)?;
+        for entity in [Entity::BOSS, Entity::PLAYER, Entity::CAT, Entity::DOG, Entity::DRAGON].iter() {
+            let at = self.get_entity_position(*entity);
+            write!(f, "_{}_{}", at.x, at.y)?;
         }
+        let playerstate = match self.playerstate {
+            PlayerState::SWORD => "S",
+            PlayerState::ARMOR => "A",
+        };
+        let color = match self.color() {
+            Color::YELLOW => "Y",
+            Color::GREEN => "G",
+            Color::RED => "R",
+            Color::BLUE => "B",
+        };
+        write!(f, "_{}_{}", playerstate, color)
     }
+}
 
-    pub fn do_solve(&mut self, game: &D15Game) {
-        let mut possible_start_moves = game.get_possible_moves();
+/// Solves a `hp_bossX_bossY_playerX_playerY_catX_catY_dogX_dogY_dragonX_dragonY_S|A_Y|G|R|B` position
+/// and returns the moves as `LEFT_UP_DOG_`-style string.
+pub fn solve_magicstr(magic_string: &str) -> Result<String, SolveError> {
+    solve_magicstr_with(magic_string, &SolveOptions::default()).map(|report| report.moves)
+}
 
-        if game.hp > 159 {
-            self.search_best = true;
-            self.besthp = game.hp-40;
-        } else if game.hp > 128 {
-            self.besthp = 100;
-        } else {
-            if game.get_boss_x() == 5 {
-                self.besthp = 54;
-            } else {
-                self.besthp = 47;
-            }
-        }
-        if game.hp <= 60 {
-            self.besthp = 35;
-        }
+/// Reads a `LEFT_UP_DOG_`-style move list back, as `solve_magicstr` writes it. The trailing `_`
+/// is optional and case doesn't matter, lines get pasted around by hand.
+pub fn parse_moves(moves: &str) -> Result<Vec<Move>, ParseError> {
+    moves.trim().split('_').filter(|part| !part.is_empty()).enumerate().map(|(index, part)| ask_move(part, index)).collect()
+}
 
-        if game.playerstate == PlayerState::SWORD {
-            let index_switch = possible_start_moves.iter().position(|x| *x == Move::SWITCH).unwrap();
-            possible_start_moves.remove(index_switch);
-            possible_start_moves.insert(0, Move::SWITCH);
-        }
+/// Plays a `LEFT_UP_DOG_`-style line on a magic string position played by `rules`, see `verify`.
+pub fn verify_magicstr(magic_string: &str, moves: &str, rules: &'static Rules) -> Result<Verification, ParseError> {
+    Ok(verify(&parse_magicstr_with_rules(magic_string, rules)?, &parse_moves(moves)?))
+}
 
-        for move_oper in possible_start_moves {
-            let mut new_game = game.clone();
-            new_game.do_move(&move_oper);
-            let moves_done = vec![move_oper];
-            self.solve(&new_game, &moves_done);
-        }
+/// Tablebase for every board reachable from the given positions played by `rules` on lines that
+/// stay above `min_hp`, and only for those, see `Tablebase`.
+pub fn generate_tablebase(magic_strings: &[&str], min_hp: i16, rules: &'static Rules) -> Result<Tablebase, ParseError> {
+    let starts = magic_strings.iter().map(|magic_string| parse_magicstr_with_rules(magic_string, rules)).collect::<Result<Vec<D15Game>, ParseError>>()?;
+    Ok(Tablebase::generate(&starts, min_hp))
+}
+
+#[wasm_bindgen]
+pub struct SolveReport {
+    moves: String,
+    hp: i16,
+    optimal: bool,
+    complete: bool,
+    steps: Vec<String>,
+    stats: SolveStats,
+}
+
+#[wasm_bindgen]
+impl SolveReport {
+    pub fn moves(&self) -> String {
+        self.moves.clone()
     }
 
-    fn solve(&mut self, game: &D15Game, moves_done: &Vec<Move>) {
-            if game.hp <= self.besthp {
-                return;
-            }
+    /// Remaining hp after the last move.
+    pub fn hp(&self) -> i16 {
+        self.hp
+    }
+
+    /// True if no line ending with more hp exists.
+    pub fn optimal(&self) -> bool {
+        self.optimal
+    }
+
+    /// False if the budget ran out and this is only the best line found until then.
+    pub fn complete(&self) -> bool {
+        self.complete
+    }
+
+    /// The board after each move as magic string, for showing the line step by step.
+    pub fn steps(&self) -> Vec<String> {
+        self.steps.clone()
+    }
+
+    /// Boards the search expanded.
+    pub fn nodes(&self) -> u64 {
+        self.stats.nodes
+    }
+
+    pub fn elapsed_ms(&self) -> f64 {
+        self.stats.elapsed.as_secs_f64() * 1000.0
+    }
+}
+
+impl SolveReport {
+    /// Everything the search counted. A solve that finds nothing has no report, its error has
+    /// the counts, see `SolveError::stats`.
+    pub fn stats(&self) -> &SolveStats {
+        &self.stats
+    }
+}
+
+fn report_for(solution: &Solution, optimal: bool, solver: &Solver) -> SolveReport {
+    SolveReport {
+        moves: print_result_moves(&solution.moves),
+        hp: solution.hp,
+        optimal,
+        complete: solver.complete(),
+        steps: solution.steps.iter().map(|step| step.game().to_string()).collect(),
+        stats: *solver.stats(),
+    }
+}
+
+fn report(solver: &Solver) -> Result<SolveReport, SolveError> {
+    let solution = solver.result()?;
+    Ok(report_for(solution, solver.proven_optimal(), solver))
+}
+
+pub fn solve_magicstr_with(magic_string: &str, options: &SolveOptions) -> Result<SolveReport, SolveError> {
+    let game = parse_magicstr_with_rules(magic_string, options.rules.unwrap_or(&DEFAULT_RULES))?;
+
+    let mut solver = Solver::with_options(options);
+    solver.do_solve(&game);
+
+    report(&solver)
+}
+
+/// Like `solve_magicstr_with`, but hands every line that improves on the ones before to
+/// `on_improvement` as soon as it is found, with its final hp and the boards expanded until then.
+pub fn solve_magicstr_anytime<F: FnMut(&str, i16, u64) + 'static>(magic_string: &str, options: &SolveOptions, mut on_improvement: F) -> Result<SolveReport, SolveError> {
+    let game = parse_magicstr_with_rules(magic_string, options.rules.unwrap_or(&DEFAULT_RULES))?;
 
-        if game.check_win_2() {
-            //print_result_moves(moves_done);
-            self.solve = Some(moves_done.to_vec());
-            self.besthp = game.hp;
-            if !self.search_best {
-                self.besthp = 201;
+    let mut solver = Solver::with_options(options);
+    solver.set_on_improvement(move |improvement| {
+        on_improvement(&print_result_moves(&improvement.solution.moves), improvement.solution.hp, improvement.nodes);
+    });
+    solver.do_solve(&game);
+
+    report(&solver)
+}
+
+/// Up to `k` lines ranked by the objective, each differing from the better ones in at least
+/// `min_difference` moves. Only the first one can be reported as optimal.
+pub fn solve_magicstr_alternatives(magic_string: &str, options: &SolveOptions, k: usize, min_difference: usize) -> Result<Vec<SolveReport>, SolveError> {
+    let game = parse_magicstr_with_rules(magic_string, options.rules.unwrap_or(&DEFAULT_RULES))?;
+
+    let mut solver = Solver::with_options(options);
+    solver.set_alternatives(k, min_difference);
+    solver.do_solve(&game);
+
+    solver.result()?;
+    Ok(solver.alternatives().iter().enumerate().map(|(rank, solution)| report_for(solution, rank == 0 && solver.proven_optimal(), &solver)).collect())
+}
+
+thread_local! {
+    static TABLEBASE: RefCell<Option<Arc<Tablebase>>> = const { RefCell::new(None) };
+    static RULES: Cell<Option<&'static Rules>> = const { Cell::new(None) };
+    /// Every distinct set of rules loaded so far, loading one of them again reuses it.
+    static LEAKED_RULES: RefCell<Vec<&'static Rules>> = const { RefCell::new(Vec::new()) };
+    static PROFILES: RefCell<Option<Arc<StrategyProfiles>>> = const { RefCell::new(None) };
+}
+
+fn loaded_tablebase() -> Option<Arc<Tablebase>> {
+    TABLEBASE.with(|tablebase| tablebase.borrow().clone())
+}
+
+fn loaded_rules() -> Option<&'static Rules> {
+    RULES.with(Cell::get)
+}
+
+fn loaded_profiles() -> Option<Arc<StrategyProfiles>> {
+    PROFILES.with(|profiles| profiles.borrow().clone())
+}
+
+/// Loads rules as JSON, see `Rules`, the `*_d15_*` functions play by them from then on. Throws if
+/// they can't be read or make no sense. Rules are kept for good once loaded, but loading the same
+/// ones again, e.g. switching back and forth, reuses them.
+#[wasm_bindgen]
+pub fn load_d15_rules(json: String) -> Result<(), JsError> {
+    let rules = Rules::from_json(&json).map_err(|e| JsError::new(&e.to_string()))?;
+    let rules = LEAKED_RULES.with(|leaked| {
+        let mut leaked = leaked.borrow_mut();
+        match leaked.iter().find(|&&each| *each == rules) {
+            Some(&known) => known,
+            None => {
+                let rules = rules.leak();
+                leaked.push(rules);
+                rules
             }
-            //println!();
-            return;
         }
+    });
+    RULES.with(|loaded| loaded.set(Some(rules)));
+    Ok(())
+}
 
-        let game_hash = calculate_hash(game);
+/// Loads strategy profiles as JSON, see `StrategyProfiles`, the `solve_d15_*` functions take their
+/// cutoffs and move ordering from them from then on. Throws if they can't be read.
+#[wasm_bindgen]
+pub fn load_d15_profiles(json: String) -> Result<(), JsError> {
+    let profiles = StrategyProfiles::from_json(&json).map_err(|e| JsError::new(&e.to_string()))?;
+    PROFILES.with(|loaded| *loaded.borrow_mut() = Some(Arc::new(profiles)));
+    Ok(())
+}
 
-        if self.checked_perms.contains(&game_hash) {
-            return;
-        }
-        self.checked_perms.insert(game_hash);
-            /*
-            let seen_boards = checked_perms_write.len();
-            if seen_boards % 1000000 == 0 {
-                println!("Seen {} boards", seen_boards);
-            }
-            */
+/// Loads a tablebase file the `solve_d15_*` functions answer from from then on, for
+/// the boards it has. Takes the file as fetched, e.g.
+/// `load_d15_tablebase(new Uint8Array(await (await fetch(url)).arrayBuffer()))`, and throws if it
+/// is corrupt or was built for another `game::RULES_VERSION`. One built for other `Rules` than
+/// `load_d15_rules` loaded still loads, but has no answers for their boards.
+#[wasm_bindgen]
+pub fn load_d15_tablebase(bytes: Vec<u8>) -> Result<usize, JsError> {
+    let tablebase = Tablebase::from_bytes(bytes).map_err(|e| JsError::new(&e.to_string()))?;
+    let boards = tablebase.len();
+    TABLEBASE.with(|loaded| *loaded.borrow_mut() = Some(Arc::new(tablebase)));
+    Ok(boards)
+}
 
-        let moves = game.get_possible_moves();
+/// Checks a shared `LEFT_UP_DOG_`-style line on a position and says whether it wins and with how
+/// much hp, or which move doesn't work there.
+#[wasm_bindgen]
+pub fn verify_d15_from_magicstr(magic_string: String, moves: String) -> Result<String, JsError> {
+    verify_magicstr(&magic_string, &moves, loaded_rules().unwrap_or(&DEFAULT_RULES)).map(|verification| verification.to_string()).map_err(|e| JsError::new(&e.to_string()))
+}
 
-        for move_oper in &moves {
-            let mut new_game = game.clone();
-            let mut new_moves_done = moves_done.to_vec();
-            new_game.do_move(move_oper);
-            new_moves_done.push(*move_oper);
-            self.solve(&new_game, &new_moves_done);
-        }
+#[wasm_bindgen]
+pub fn solve_d15_from_magicstr(magic_string : String) -> Result<String, JsError> {
+    let options = SolveOptions { tablebase: loaded_tablebase(), rules: loaded_rules(), profiles: loaded_profiles(), ..SolveOptions::default() };
+    match solve_magicstr_with(&magic_string, &options) {
+        Ok(report) => Ok(report.moves),
+        // the page shows this like any other answer
+        Err(e @ SolveError::Unsolvable { .. }) => Ok(e.to_string()),
+        Err(e) => Err(JsError::new(&e.to_string())),
     }
 }
 
-fn print_result_moves(moves: &Vec<Move>) -> String {
+#[wasm_bindgen]
+pub fn solve_d15_exact_from_magicstr(magic_string : String) -> Result<SolveReport, JsError> {
+    let options = SolveOptions { mode: SolveMode::Exact, tablebase: loaded_tablebase(), rules: loaded_rules(), profiles: loaded_profiles(), ..SolveOptions::default() };
+    solve_magicstr_with(&magic_string, &options).map_err(|e| JsError::new(&e.to_string()))
+}
+
+#[wasm_bindgen]
+pub fn solve_d15_alternatives(magic_string : String, mode: SolveMode, k: usize, min_difference: usize) -> Result<Vec<SolveReport>, JsError> {
+    let options = SolveOptions { mode, tablebase: loaded_tablebase(), rules: loaded_rules(), profiles: loaded_profiles(), ..SolveOptions::default() };
+    solve_magicstr_alternatives(&magic_string, &options, k, min_difference).map_err(|e| JsError::new(&e.to_string()))
+}
+
+#[wasm_bindgen]
+pub fn solve_d15_with(magic_string : String, mode: SolveMode, strategy: SearchStrategy) -> Result<SolveReport, JsError> {
+    let options = SolveOptions { mode, strategy, tablebase: loaded_tablebase(), rules: loaded_rules(), profiles: loaded_profiles(), ..SolveOptions::default() };
+    solve_magicstr_with(&magic_string, &options).map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// Minimizes `hp_weight * spent hp + move_weight * moves + risky_weight * (SWITCH and PASSTURN moves)`.
+#[wasm_bindgen]
+pub fn solve_d15_weighted(magic_string : String, mode: SolveMode, strategy: SearchStrategy, hp_weight: u32, move_weight: u32, risky_weight: u32) -> Result<SolveReport, JsError> {
+    let objective = SolveObjective::Weighted { hp: hp_weight, moves: move_weight, risky: risky_weight };
+    let options = SolveOptions { mode, strategy, objective, tablebase: loaded_tablebase(), rules: loaded_rules(), profiles: loaded_profiles(), ..SolveOptions::default() };
+    solve_magicstr_with(&magic_string, &options).map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// Stops after `max_nodes` expanded boards or `time_limit_ms` milliseconds, 0 leaves either unlimited.
+/// Check `complete()` on the result to see whether it ran out.
+#[wasm_bindgen]
+pub fn solve_d15_budgeted(magic_string : String, mode: SolveMode, strategy: SearchStrategy, max_nodes: u32, time_limit_ms: u32) -> Result<SolveReport, JsError> {
+    let budget = SolveBudget {
+        max_nodes: if max_nodes > 0 { Some(max_nodes as u64) } else { None },
+        time_limit: if time_limit_ms > 0 { Some(Duration::from_millis(time_limit_ms as u64)) } else { None },
+        cancel: None,
+    };
+    let options = SolveOptions { mode, strategy, budget, tablebase: loaded_tablebase(), rules: loaded_rules(), profiles: loaded_profiles(), ..SolveOptions::default() };
+    solve_magicstr_with(&magic_string, &options).map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// Calls `on_improvement(moves, hp, nodes)` with every better line while the search runs, which
+/// makes sense from a worker that posts them on. A `time_limit_ms` of 0 leaves the search unlimited.
+#[wasm_bindgen]
+pub fn solve_d15_anytime(magic_string : String, mode: SolveMode, strategy: SearchStrategy, time_limit_ms: u32, on_improvement: js_sys::Function) -> Result<SolveReport, JsError> {
+    let budget = SolveBudget {
+        time_limit: if time_limit_ms > 0 { Some(Duration::from_millis(time_limit_ms as u64)) } else { None },
+        ..SolveBudget::default()
+    };
+    let options = SolveOptions { mode, strategy, budget, tablebase: loaded_tablebase(), rules: loaded_rules(), profiles: loaded_profiles(), ..SolveOptions::default() };
+    solve_magicstr_anytime(&magic_string, &options, move |moves, hp, nodes| {
+        // a throwing callback shouldn't abort the search
+        let _ = on_improvement.call3(&JsValue::NULL, &JsValue::from_str(moves), &JsValue::from(hp), &JsValue::from(nodes as f64));
+    }).map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// Writes moves as `LEFT_UP_DOG_`-style text, what `parse_moves` reads.
+pub fn print_result_moves(moves: &[Move]) -> String {
     let mut parts : Vec<&str> = vec![];
     for move_oper in moves {
         match move_oper {
@@ -176,3 +439,43 @@ fn print_result_moves(moves: &Vec<Move>) -> String {
     parts.join("")
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_MOVES: [Move; 9] = [Move::PASSTURN, Move::LEFT, Move::RIGHT, Move::UP, Move::DOWN, Move::DOG, Move::CAT, Move::DRAGON, Move::SWITCH];
+
+    #[test]
+    fn moves_round_trip() {
+        let line = print_result_moves(&ALL_MOVES);
+        assert_eq!(line, "PASSTURN_LEFT_RIGHT_UP_DOWN_DOG_CAT_DRAGON_SWITCH_");
+        assert_eq!(parse_moves(&line).unwrap(), ALL_MOVES);
+    }
+
+    #[test]
+    fn parse_moves_takes_pasted_lines() {
+        assert_eq!(parse_moves(" left_Up_dog ").unwrap(), [Move::LEFT, Move::UP, Move::DOG]);
+        assert_eq!(parse_moves("").unwrap(), []);
+        assert_eq!(parse_moves("LEFT_JUMP_").unwrap_err(), ParseError::InvalidMove { index: 1, value: "JUMP".to_string() });
+    }
+
+    #[test]
+    fn magic_strings_round_trip() {
+        let magic_string = "60_4_3_4_4_5_3_3_3_5_4_A_Y";
+        assert_eq!(magic_string.parse::<D15Game>().unwrap().to_string(), magic_string);
+    }
+
+    #[test]
+    fn parse_refuses_coordinates_outside_the_room() {
+        assert_eq!("60_4_3_4_8_5_3_3_3_5_4_A_Y".parse::<D15Game>().unwrap_err(), ParseError::CoordinateOutOfRange { index: 4, value: 8, max: ROOM_MAX_Y });
+    }
+
+    #[test]
+    fn solve_says_what_is_wrong_with_the_input() {
+        assert_eq!(solve_magicstr("60_4_3_4_4"), Err(SolveError::Parse(ParseError::FieldCount { found: 5 })));
+        assert_eq!(solve_magicstr("60_4_3_4_4_5_3_3_3_5_4_X_Y"), Err(SolveError::Parse(ParseError::InvalidPlayerState { index: 11, value: "X".to_string() })));
+        assert_eq!(solve_magicstr("60_4_3_4_4_5_3_3_3_5_4_A_Q"), Err(SolveError::Parse(ParseError::InvalidColor { index: 12, value: "Q".to_string() })));
+        assert_eq!(solve_magicstr("sixty_4_3_4_4_5_3_3_3_5_4_A_Y"), Err(SolveError::Parse(ParseError::InvalidNumber { index: 0, value: "sixty".to_string() })));
+        assert_eq!(solve_magicstr("250_4_3_4_4_5_3_3_3_5_4_A_Y"), Err(SolveError::Parse(ParseError::HpOutOfRange { index: 0, hp: 250, min: 50, max: 200 })));
+    }
+}
@@ -0,0 +1,275 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ed15r::profiles::StrategyProfiles;
+use ed15r::{parse_magicstr_with_rules, parse_moves, solve_magicstr_alternatives, solve_magicstr_anytime, solve_magicstr_with, verify_magicstr, MoveOrdering, SearchStrategy, SolveMode, SolveObjective, SolveOptions, SolveError, SolveReport, SolveStats, SearchTrace, Rules, Tablebase, DEFAULT_RULES};
+
+fn usage(program: &str) -> ! {
+    eprintln!("usage: {} [--exact] [--astar] [--anytime] [--objective=hp|moves|risky|HP,MOVES,RISKY] [--ordering=tuned|late-pass|legal] [--profiles=FILE] [--alternatives=K] [--min-difference=N] [--max-nodes=N] [--time-limit-ms=N] [--threads=N] [--tablebase=FILE] [--stats] [--trace=FILE|-] [--steps] [--verify=MOVES [--explain]] [--death-hp=N] [--rules=FILE] [--] <magic string>", program);
+    process::exit(2);
+}
+
+fn number_flag<T: std::str::FromStr>(program: &str, value: &str) -> T {
+    value.parse().unwrap_or_else(|_| usage(program))
+}
+
+fn objective_flag(program: &str, value: &str) -> SolveObjective {
+    match value {
+        "hp" => SolveObjective::MaxHp,
+        "moves" => SolveObjective::FewestMoves,
+        "risky" => SolveObjective::FewestRiskyMoves,
+        _ => {
+            let weights: Vec<u32> = value.split(',').map(|weight| number_flag(program, weight)).collect();
+            match weights.as_slice() {
+                [hp, moves, risky] => SolveObjective::Weighted { hp: *hp, moves: *moves, risky: *risky },
+                _ => usage(program),
+            }
+        }
+    }
+}
+
+fn ordering_flag(program: &str, value: &str) -> MoveOrdering {
+    match value {
+        "tuned" => MoveOrdering::Tuned { late_pass: false },
+        "late-pass" => MoveOrdering::Tuned { late_pass: true },
+        "legal" => MoveOrdering::Legal,
+        _ => usage(program),
+    }
+}
+
+fn load_tablebase(path: &str) -> Tablebase {
+    Tablebase::open(Path::new(path)).unwrap_or_else(|e| {
+        eprint!("Could not load tablebase {} - {}", path, e);
+        process::exit(1);
+    })
+}
+
+/// TOML if the file ends in `.toml`, JSON otherwise.
+fn load_rules(path: &str) -> &'static Rules {
+    Rules::open(Path::new(path)).unwrap_or_else(|e| {
+        eprint!("Could not load rules {} - {}", path, e);
+        process::exit(1);
+    }).leak()
+}
+
+fn load_profiles(path: &str) -> StrategyProfiles {
+    StrategyProfiles::open(Path::new(path)).unwrap_or_else(|e| {
+        eprint!("Could not load profiles {} - {}", path, e);
+        process::exit(1);
+    })
+}
+
+/// `-` traces to stderr.
+fn open_trace(path: &str) -> SearchTrace {
+    if path == "-" {
+        return SearchTrace::new(io::stderr());
+    }
+    let file = File::create(path).unwrap_or_else(|e| {
+        eprint!("Could not create trace {} - {}", path, e);
+        process::exit(1);
+    });
+    SearchTrace::new(BufWriter::new(file))
+}
+
+/// What each move of a verified line did, the pets' reasons included.
+fn print_explanation(magic_string: &str, moves: &str, played: usize, rules: &'static Rules) {
+    let (mut game, moves) = match (parse_magicstr_with_rules(magic_string, rules), parse_moves(moves)) {
+        (Ok(game), Ok(moves)) => (game, moves),
+        _ => return,
+    };
+    match game.moves_until_next_phase() {
+        Some(moves) => print!("\nphase {} at {} hp, {} moves until the next one", game.phase(), game.hp, moves),
+        None => print!("\nphase {} at {} hp", game.phase(), game.hp),
+    }
+    for (index, move_oper) in moves.iter().take(played).enumerate() {
+        print!("\n{}. {:?}", index + 1, move_oper);
+        for event in game.do_move_logged(move_oper) {
+            print!("\n   {}", event);
+        }
+    }
+}
+
+/// On stderr, stdout is the answer the runner hands on. Failed solves have them too, they tell why.
+fn print_stats(stats: Option<&SolveStats>) {
+    if let Some(stats) = stats {
+        eprintln!("{}", stats);
+    }
+}
+
+fn print_report(report: &SolveReport) {
+    let note = if report.optimal() {
+        " (proven optimal)"
+    } else if !report.complete() {
+        " (incomplete)"
+    } else {
+        ""
+    };
+    print!("{}\nfinal hp: {}{}", report.moves(), report.hp(), note);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = &args[0];
+
+    let mut options = SolveOptions::default();
+    let mut alternatives = 1;
+    let mut min_difference = 0;
+    let mut anytime = false;
+    let mut stats = false;
+    let mut steps = false;
+    let mut verify_moves = None;
+    let mut explain = false;
+    let mut trace = None;
+    let mut magic_string = None;
+    let mut flags_done = false;
+    for arg in &args[1..] {
+        if flags_done {
+            if magic_string.is_some() {
+                usage(program);
+            }
+            magic_string = Some(arg);
+            continue;
+        }
+        let (flag, value) = match arg.find('=') {
+            Some(at) => (&arg[..at], &arg[at + 1..]),
+            None => (arg.as_str(), ""),
+        };
+        match flag {
+            "--exact" => options.mode = SolveMode::Exact,
+            "--astar" => options.strategy = SearchStrategy::AStar,
+            "--anytime" => anytime = true,
+            "--tablebase" => options.tablebase = Some(Arc::new(load_tablebase(value))),
+            "--stats" => stats = true,
+            "--steps" => steps = true,
+            "--verify" => verify_moves = Some(value),
+            "--explain" => explain = true,
+            "--trace" => trace = Some(value),
+            "--objective" => options.objective = objective_flag(program, value),
+            "--ordering" => options.ordering = Some(ordering_flag(program, value)),
+            "--max-nodes" => options.budget.max_nodes = Some(number_flag(program, value)),
+            "--time-limit-ms" => options.budget.time_limit = Some(Duration::from_millis(number_flag(program, value))),
+            "--threads" => options.threads = number_flag(program, value),
+            "--death-hp" => options.death_hp = Some(number_flag(program, value)),
+            "--rules" => options.rules = Some(load_rules(value)),
+            "--profiles" => options.profiles = Some(Arc::new(load_profiles(value))),
+            "--alternatives" => alternatives = number_flag(program, value),
+            "--min-difference" => min_difference = number_flag(program, value),
+            "--" => flags_done = true,
+            _ if magic_string.is_none() && !arg.starts_with('-') => magic_string = Some(arg),
+            _ => usage(program),
+        }
+    }
+    let magic_string = match magic_string {
+        Some(magic_string) => magic_string,
+        None => usage(program),
+    };
+    if let Err(e) = parse_magicstr_with_rules(magic_string, options.rules.unwrap_or(&DEFAULT_RULES)) {
+        eprint!("Invalid input - {}", e);
+        process::exit(1);
+    }
+    // only once everything else checked out, so a bad request never creates or truncates a file
+    options.trace = trace.map(open_trace);
+
+    if let Some(moves) = verify_moves {
+        // plays the given line instead of searching one
+        let rules = options.rules.unwrap_or(&DEFAULT_RULES);
+        match verify_magicstr(magic_string, moves, rules) {
+            Ok(verification) => {
+                print!("{}", verification);
+                if explain {
+                    print_explanation(magic_string, moves, verification.steps.len(), rules);
+                }
+                if !verification.valid() {
+                    process::exit(1);
+                }
+            },
+            Err(e) => {
+                eprint!("Invalid input - {}", e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if alternatives > 1 || min_difference > 0 {
+        match solve_magicstr_alternatives(magic_string, &options, alternatives, min_difference) {
+            Ok(reports) => {
+                for (rank, report) in reports.iter().enumerate() {
+                    print!("{}#{} ", if rank > 0 { "\n\n" } else { "" }, rank + 1);
+                    print_report(report);
+                }
+                if stats {
+                    print_stats(Some(reports[0].stats()));
+                }
+            },
+            Err(e) => {
+                if stats {
+                    print_stats(e.stats());
+                }
+                eprint!("{}", e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if anytime {
+        // one `<moves> <hp> <nodes>` line per improvement, the last one is the answer
+        let result = solve_magicstr_anytime(magic_string, &options, |moves, hp, nodes| {
+            let mut stdout = io::stdout();
+            let _ = writeln!(stdout, "{} {} {}", moves, hp, nodes);
+            let _ = stdout.flush();
+        });
+        match result {
+            Ok(report) if stats => print_stats(Some(report.stats())),
+            Ok(_) => {},
+            Err(e) => {
+                if stats {
+                    print_stats(e.stats());
+                }
+                eprint!("{}", e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    match solve_magicstr_with(magic_string, &options) {
+        Ok(report) => {
+            if stats {
+                print_stats(Some(report.stats()));
+            }
+            let plain = options.mode == SolveMode::Fast && options.strategy == SearchStrategy::DepthFirst && options.objective == SolveObjective::MaxHp;
+            if !plain {
+                print_report(&report);
+            } else {
+                print!("{}", report.moves());
+            }
+            if steps {
+                // the board after each move, as magic string
+                for board in report.steps() {
+                    print!("\n{}", board);
+                }
+            }
+        },
+        // an answer like any other for the runner, not a failure
+        Err(e @ SolveError::Unsolvable { .. }) => {
+            if stats {
+                print_stats(e.stats());
+            }
+            print!("{}", e);
+        },
+        Err(e) => {
+            if stats {
+                print_stats(e.stats());
+            }
+            eprint!("{}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Mutex;
+use std::thread;
+
+use hash_hasher::HashBuildHasher;
+
+use crate::budget::BudgetTracker;
+use crate::game::{D15Game, Move, StateKey};
+use crate::profiles::StrategyProfile;
+use crate::solver::{Solution, SolveObjective};
+use crate::stats::{SearchTrace, SolveStats, TraceEvent};
+
+/// The transposition table is split into this many maps, each behind its own lock.
+const SHARDS: usize = 64;
+
+/// Start move a seeded line counts as coming from. It's behind every real one, so a line of the same
+/// cost from any start move still replaces it.
+const SEED_OWNER: usize = 0xff;
+
+/// Orders lines across workers: cheaper first, ties go to the earlier start move. Costs past 2^56
+/// all rank the same, no line gets anywhere near that.
+fn rank(cost: u64, owner: usize) -> u64 {
+    cost.min(u64::MAX >> 8) << 8 | owner as u64
+}
+
+type Shard = Mutex<HashMap<StateKey, (u64, usize), HashBuildHasher>>;
+
+/// Cost each board was reached at, and from which start move.
+struct SharedTable {
+    shards: Vec<Shard>,
+}
+
+impl SharedTable {
+    fn new() -> SharedTable {
+        SharedTable {
+            shards: (0..SHARDS).map(|_| Mutex::new(HashMap::with_capacity_and_hasher(1000000 / SHARDS, HashBuildHasher::default()))).collect(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().unwrap().len()).sum()
+    }
+
+    /// True if the same or an earlier start move got to the board at no higher cost, otherwise records
+    /// it for `owner`. A later start move never cuts off an earlier one, so what an earlier start move
+    /// finds doesn't depend on how far the others got.
+    fn seen(&self, game_key: StateKey, cost: u64, owner: usize) -> bool {
+        // the low bits pick the bucket inside the shard
+        let mut shard = self.shards[(game_key.mixed() >> 32) as usize % SHARDS].lock().unwrap();
+        match shard.get(&game_key) {
+            Some(&(seen_cost, by)) if by <= owner && seen_cost <= cost => true,
+            _ => {
+                shard.insert(game_key, (cost, owner));
+                false
+            }
+        }
+    }
+}
+
+struct Shared<'a> {
+    game: &'a D15Game,
+    start_moves: Vec<Move>,
+    next_start: AtomicUsize,
+    table: SharedTable,
+    objective: SolveObjective,
+    profile: StrategyProfile,
+    besthp: i16,
+    search_best: bool,
+    /// `rank` of the best line while `search_best`, otherwise the earliest start move that won.
+    /// Only written with `best` locked.
+    bound: AtomicU64,
+    best: Mutex<Option<Solution>>,
+    trace: Option<&'a SearchTrace>,
+}
+
+struct Worker<'a> {
+    shared: &'a Shared<'a>,
+    owner: usize,
+    besthp: i16,
+    tracker: BudgetTracker,
+    improvements: Sender<(Solution, u64)>,
+    /// Of the current start move, `total` has the ones before.
+    stats: SolveStats,
+    total: SolveStats,
+}
+
+impl Worker<'_> {
+    fn run(&mut self) {
+        let shared = self.shared;
+        loop {
+            self.owner = shared.next_start.fetch_add(1, Ordering::Relaxed);
+            let move_oper = match shared.start_moves.get(self.owner) {
+                Some(&move_oper) => move_oper,
+                None => return,
+            };
+            self.besthp = shared.besthp;
+
+            let mut new_game = shared.game.clone();
+            new_game.do_move(&move_oper);
+            let cost = shared.objective.move_cost(move_oper, shared.game.hp - new_game.hp);
+            self.stats = SolveStats::default();
+            self.solve(&new_game, &[move_oper], cost);
+
+            self.stats.elapsed = self.tracker.elapsed();
+            if let Some(trace) = shared.trace {
+                self.stats.peak_table_size = shared.table.len();
+                trace.record(&TraceEvent::StartMove { start_move: move_oper, stats: &self.stats });
+            }
+            self.total.merge(&self.stats);
+        }
+    }
+
+    /// Whether a line from this start move at `cost` can't replace the best one anymore.
+    fn beaten(&self, cost: u64) -> bool {
+        let bound = self.shared.bound.load(Ordering::Relaxed);
+        if self.shared.search_best {
+            rank(cost, self.owner) >= bound
+        } else {
+            self.owner as u64 >= bound
+        }
+    }
+
+    fn offer(&mut self, solution: Solution) {
+        let shared = self.shared;
+        let key = if shared.search_best { rank(solution.cost, self.owner) } else { self.owner as u64 };
+
+        let mut best = shared.best.lock().unwrap();
+        if key >= shared.bound.load(Ordering::Relaxed) {
+            return;
+        }
+        shared.bound.store(key, Ordering::Relaxed);
+        // without search_best an earlier start move may still win with less hp, only the final pick
+        // gets reported. A line as cheap from an earlier start move is picked but isn't better.
+        if shared.search_best && best.as_ref().is_none_or(|best| solution.cost < best.cost) {
+            let _ = self.improvements.send((solution.clone(), self.tracker.nodes()));
+        }
+        *best = Some(solution);
+    }
+
+    fn solve(&mut self, game: &D15Game, moves_done: &[Move], cost: u64) {
+        if self.tracker.stopped().is_some() || self.beaten(cost) {
+            return;
+        }
+        if game.hp <= self.besthp {
+            self.stats.besthp_prunes += 1;
+            return;
+        }
+        if game.is_dead() {
+            return;
+        }
+
+        if game.check_win_2() {
+            self.offer(Solution::new(self.shared.game, moves_done.to_vec(), cost));
+            if !self.shared.search_best {
+                // above every hp a board can have, nothing gets searched any more
+                self.besthp = game.rules().start_hp_max + 1;
+            }
+            return;
+        }
+
+        if self.shared.table.seen(game.key(), cost, self.owner) {
+            self.stats.transposition_hits += 1;
+            return;
+        }
+        if !self.tracker.expand() {
+            return;
+        }
+        self.stats.nodes += 1;
+        self.stats.max_depth = self.stats.max_depth.max(moves_done.len());
+
+        for move_oper in self.shared.profile.moves(game) {
+            let mut new_game = game.clone();
+            let mut new_moves_done = moves_done.to_vec();
+            new_game.do_move(&move_oper);
+            new_moves_done.push(move_oper);
+            let new_cost = cost.saturating_add(self.shared.objective.move_cost(move_oper, game.hp - new_game.hp));
+            self.solve(&new_game, &new_moves_done, new_cost);
+        }
+    }
+}
+
+/// The depth-first search of `Solver::solve` with the start moves spread over `threads` threads,
+/// which share the transposition table and the best line. `seed` is a line to beat, e.g. the fast one.
+///
+/// The pick doesn't depend on thread timing: the cheapest line wins and ties go to the earliest start
+/// move, the one that got there first in its own subtree. Without `search_best` the earliest start
+/// move that wins at all is taken, the later ones stop once it has.
+///
+/// `on_improvement` runs on the calling thread with every better line while `search_best`. The
+/// workers' counts get added to `stats`, each start move is traced from the worker that searched it.
+#[allow(clippy::too_many_arguments)]
+pub fn parallel_search(game: &D15Game, objective: SolveObjective, profile: StrategyProfile, besthp: i16, search_best: bool, seed: Option<Solution>, threads: usize, tracker: &mut BudgetTracker, stats: &mut SolveStats, trace: Option<&SearchTrace>, on_improvement: &mut dyn FnMut(&Solution, u64)) -> Option<Solution> {
+    let start_moves = profile.start_moves(game);
+    let threads = threads.min(start_moves.len()).max(1);
+    let shared = Shared {
+        game,
+        start_moves,
+        next_start: AtomicUsize::new(0),
+        table: SharedTable::new(),
+        objective,
+        profile,
+        besthp,
+        search_best,
+        bound: AtomicU64::new(seed.as_ref().map_or(u64::MAX, |seed| rank(seed.cost, SEED_OWNER))),
+        best: Mutex::new(seed),
+        trace,
+    };
+
+    let (improvements, received) = channel();
+    let workers = thread::scope(|scope| {
+        let handles: Vec<_> = tracker.fork(threads).into_iter().map(|worker_tracker| {
+            let mut worker = Worker { shared: &shared, owner: 0, besthp, tracker: worker_tracker, improvements: improvements.clone(), stats: SolveStats::default(), total: SolveStats::default() };
+            scope.spawn(move || {
+                worker.run();
+                (worker.tracker, worker.total)
+            })
+        }).collect();
+        drop(improvements);
+
+        for (solution, nodes) in received {
+            on_improvement(&solution, nodes);
+        }
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect::<Vec<_>>()
+    });
+    let (workers, worker_stats): (Vec<BudgetTracker>, Vec<SolveStats>) = workers.into_iter().unzip();
+    tracker.join(workers);
+    for worker_stats in &worker_stats {
+        stats.merge(worker_stats);
+    }
+    stats.peak_table_size = stats.peak_table_size.max(shared.table.len());
+
+    shared.best.into_inner().unwrap()
+}
@@ -0,0 +1,399 @@
+//! What the pets do after each move, put together from a few kinds of moves, see `PetSchedule`.
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use crate::events::GameEvent;
+use crate::game::{D15Game, Entity, Phase, Point};
+use crate::game::Entity::{DOG, DRAGON, PLAYER};
+
+/// One kind of pet move, e.g. stepping towards another piece. Which pet does it comes from the
+/// `PetSchedule`, so the same one can serve every pet.
+///
+/// Behaviours that come with the crate: `ColorJump`, `StepToward`, `StepAway`, `Slide`,
+/// `SwapWith`, `MirrorBy`, `MirrorByRoomCenter` and `Rest`.
+pub trait PetBehaviour: Sync {
+    /// Moves `pet` on `game` after the player's move, or leaves it where it is. What the search
+    /// calls, as often as it plays a move.
+    fn act(&self, game: &mut D15Game, pet: Entity);
+
+    /// `act`, telling what it did into `log`. Without it the pet's moves just don't show up in
+    /// `D15Game::do_move_logged`.
+    fn act_logged(&self, game: &mut D15Game, pet: Entity, _log: &mut PetLog) {
+        self.act(game, pet);
+    }
+}
+
+/// Where a `PetBehaviour` tells what it did, see `D15Game::do_move_logged`.
+pub struct PetLog<'a> {
+    events: &'a mut Vec<GameEvent>,
+    phase: Phase,
+}
+
+impl PetLog<'_> {
+    pub(crate) fn new(events: &mut Vec<GameEvent>, phase: Phase) -> PetLog<'_> {
+        PetLog { events, phase }
+    }
+
+    /// Phase the pets move in.
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub fn record(&mut self, event: GameEvent) {
+        self.events.push(event);
+    }
+
+    /// The phase in front of `rule`, how pet events name their reason.
+    fn rule(&self, rule: fmt::Arguments) -> String {
+        format!("pattern {}: {}", self.phase, rule)
+    }
+
+    /// `pet` going to `to` by `rule`, or staying if that's where it is.
+    pub fn moved(&mut self, game: &D15Game, pet: Entity, to: &Point, rule: fmt::Arguments) {
+        let from = game.get_entity_position(pet);
+        let rule = self.rule(rule);
+        self.record(if from == to {
+            GameEvent::Stayed { entity: pet, rule }
+        } else {
+            GameEvent::Moved { entity: pet, from: from.clone(), to: to.clone(), rule }
+        });
+    }
+
+    /// `pet` wanted to go to `target`, but `can_move_to` says no.
+    pub fn blocked(&mut self, game: &D15Game, pet: Entity, target: &Point) {
+        self.record(GameEvent::Blocked { entity: pet, target: target.clone(), by: game.blocker(pet, target) });
+    }
+
+    pub fn stayed(&mut self, pet: Entity, rule: fmt::Arguments) {
+        let rule = self.rule(rule);
+        self.record(GameEvent::Stayed { entity: pet, rule });
+    }
+
+    pub fn swapped(&mut self, pet: Entity, other: Entity) {
+        let rule = format!("pattern {}", self.phase);
+        self.record(GameEvent::Swapped { first: pet, second: other, rule });
+    }
+}
+
+/// What the behaviours here tell to: a `PetLog`, or `()` during the search, which drops it all
+/// before anything gets formatted. Like `EventLog` for the rest of a move.
+trait PetEvents {
+    fn moved(&mut self, game: &D15Game, pet: Entity, to: &Point, rule: fmt::Arguments);
+    fn blocked(&mut self, game: &D15Game, pet: Entity, target: &Point);
+    fn stayed(&mut self, pet: Entity, rule: fmt::Arguments);
+    fn swapped(&mut self, pet: Entity, other: Entity);
+}
+
+impl PetEvents for () {
+    #[inline(always)]
+    fn moved(&mut self, _game: &D15Game, _pet: Entity, _to: &Point, _rule: fmt::Arguments) {}
+    #[inline(always)]
+    fn blocked(&mut self, _game: &D15Game, _pet: Entity, _target: &Point) {}
+    #[inline(always)]
+    fn stayed(&mut self, _pet: Entity, _rule: fmt::Arguments) {}
+    #[inline(always)]
+    fn swapped(&mut self, _pet: Entity, _other: Entity) {}
+}
+
+impl PetEvents for PetLog<'_> {
+    fn moved(&mut self, game: &D15Game, pet: Entity, to: &Point, rule: fmt::Arguments) {
+        PetLog::moved(self, game, pet, to, rule);
+    }
+    fn blocked(&mut self, game: &D15Game, pet: Entity, target: &Point) {
+        PetLog::blocked(self, game, pet, target);
+    }
+    fn stayed(&mut self, pet: Entity, rule: fmt::Arguments) {
+        PetLog::stayed(self, pet, rule);
+    }
+    fn swapped(&mut self, pet: Entity, other: Entity) {
+        PetLog::swapped(self, pet, other);
+    }
+}
+
+/// `PetBehaviour` for the behaviours here, both ways through the same `run`.
+macro_rules! pet_behaviours {
+    ($($behaviour:ty),*) => {$(
+        impl PetBehaviour for $behaviour {
+            fn act(&self, game: &mut D15Game, pet: Entity) {
+                self.run(game, pet, &mut ());
+            }
+
+            fn act_logged(&self, game: &mut D15Game, pet: Entity, log: &mut PetLog) {
+                self.run(game, pet, log);
+            }
+        }
+    )*};
+}
+
+pet_behaviours!(ColorJump, StepToward, StepAway, Slide, SwapWith, MirrorBy, MirrorByRoomCenter, Rest);
+
+/// Jumps `forward` tiles the way the color of the floor it stands on says for the board's `Color`,
+/// or `back` tiles the other way if that's blocked.
+pub struct ColorJump {
+    pub forward: i8,
+    pub back: i8,
+}
+
+impl ColorJump {
+    fn run<L: PetEvents>(&self, game: &mut D15Game, pet: Entity, log: &mut L) {
+        let at = game.get_entity_position(pet).clone();
+        let (x_dir, y_dir) = game.color_jump_direction(&at);
+        let forward = Point { x: at.x + self.forward * x_dir, y: at.y + self.forward * y_dir };
+        if game.can_move_to(&forward, pet) {
+            log.moved(game, pet, &forward, format_args!("{} tiles the way its floor color says", self.forward));
+            game.set_entity_position(pet, forward);
+            return;
+        }
+        log.blocked(game, pet, &forward);
+        let back = Point { x: at.x - self.back * x_dir, y: at.y - self.back * y_dir };
+        if game.can_move_to(&back, pet) {
+            log.moved(game, pet, &back, format_args!("fell back to {} tiles the other way", self.back));
+            game.set_entity_position(pet, back);
+        } else {
+            log.blocked(game, pet, &back);
+            log.stayed(pet, format_args!("blocked both ways"));
+        }
+    }
+}
+
+/// How rules name a piece, "towards the dragon".
+fn name(entity: Entity) -> &'static str {
+    match entity {
+        PLAYER => "player",
+        Entity::BOSS => "boss",
+        Entity::CAT => "cat",
+        DOG => "dog",
+        DRAGON => "dragon",
+    }
+}
+
+/// The tile 1 step from `from` along the axis `diff` is longer on, y if both are as long.
+fn step_along(from: &Point, diff_x: i8, diff_y: i8) -> Point {
+    if diff_x.abs() > diff_y.abs() {
+        Point { x: from.x + diff_x.signum(), y: from.y }
+    } else {
+        Point { x: from.x, y: from.y + diff_y.signum() }
+    }
+}
+
+/// 1 tile towards `target`, unless it's next to it already, diagonals count.
+pub struct StepToward {
+    pub target: Entity,
+}
+
+impl StepToward {
+    fn run<L: PetEvents>(&self, game: &mut D15Game, pet: Entity, log: &mut L) {
+        let at = game.get_entity_position(pet);
+        let target = game.get_entity_position(self.target);
+        let diff_x = target.x - at.x;
+        let diff_y = target.y - at.y;
+        if !(diff_x.abs() > 1 || diff_y.abs() > 1) {
+            log.stayed(pet, format_args!("already next to the {}", name(self.target)));
+            return;
+        }
+        let step = step_along(at, diff_x, diff_y);
+        if game.can_move_to(&step, pet) {
+            log.moved(game, pet, &step, format_args!("1 tile towards the {}", name(self.target)));
+            game.set_entity_position(pet, step);
+        } else {
+            log.blocked(game, pet, &step);
+            log.stayed(pet, format_args!("the way to the {} is blocked", name(self.target)));
+        }
+    }
+}
+
+/// 1 tile away from `from`.
+pub struct StepAway {
+    pub from: Entity,
+}
+
+impl StepAway {
+    fn run<L: PetEvents>(&self, game: &mut D15Game, pet: Entity, log: &mut L) {
+        let at = game.get_entity_position(pet);
+        let from = game.get_entity_position(self.from);
+        let step = step_along(at, at.x - from.x, at.y - from.y);
+        if game.can_move_to(&step, pet) {
+            log.moved(game, pet, &step, format_args!("1 tile away from the {}", name(self.from)));
+            game.set_entity_position(pet, step);
+        } else {
+            log.blocked(game, pet, &step);
+            log.stayed(pet, format_args!("the way away from the {} is blocked", name(self.from)));
+        }
+    }
+}
+
+/// Up to `steps` tiles by `step_x`, `step_y` each, as far as it gets before something's in the way.
+pub struct Slide {
+    pub step_x: i8,
+    pub step_y: i8,
+    pub steps: u8,
+}
+
+impl Slide {
+    fn direction(&self) -> &'static str {
+        match (self.step_x.signum(), self.step_y.signum()) {
+            (0, 1) => "up",
+            (0, -1) => "down",
+            (1, 0) => "right",
+            (-1, 0) => "left",
+            _ => "diagonally",
+        }
+    }
+
+    fn run<L: PetEvents>(&self, game: &mut D15Game, pet: Entity, log: &mut L) {
+        let mut reached = game.get_entity_position(pet).clone();
+        for _ in 0..self.steps {
+            let next = Point { x: reached.x + self.step_x, y: reached.y + self.step_y };
+            if !game.can_move_to(&next, pet) {
+                log.blocked(game, pet, &next);
+                break;
+            }
+            reached = next;
+        }
+        log.moved(game, pet, &reached, format_args!("up to {} tiles {}", self.steps, self.direction()));
+        game.set_entity_position(pet, reached);
+    }
+}
+
+/// Trades places with `other`, nothing can block that.
+pub struct SwapWith {
+    pub other: Entity,
+}
+
+impl SwapWith {
+    fn run<L: PetEvents>(&self, game: &mut D15Game, pet: Entity, log: &mut L) {
+        game.swap_entities(pet, self.other);
+        log.swapped(pet, self.other);
+    }
+}
+
+/// To the tile on the other side of `center`, as far from it as before.
+pub struct MirrorBy {
+    pub center: Entity,
+}
+
+impl MirrorBy {
+    fn run<L: PetEvents>(&self, game: &mut D15Game, pet: Entity, log: &mut L) {
+        let mirror = game.get_entity_position(pet).mirror_by(game.get_entity_position(self.center));
+        if game.can_move_to(&mirror, pet) {
+            log.moved(game, pet, &mirror, format_args!("mirrored at the {}", name(self.center)));
+            game.set_entity_position(pet, mirror);
+        } else {
+            log.blocked(game, pet, &mirror);
+            log.stayed(pet, format_args!("its mirror tile at the {} is blocked", name(self.center)));
+        }
+    }
+}
+
+/// To the tile on the other side of the middle of the room.
+pub struct MirrorByRoomCenter;
+
+impl MirrorByRoomCenter {
+    fn run<L: PetEvents>(&self, game: &mut D15Game, pet: Entity, log: &mut L) {
+        let mirror = game.get_entity_position(pet).mirror_by_roomcenter();
+        if game.can_move_to(&mirror, pet) {
+            log.moved(game, pet, &mirror, format_args!("mirrored at the room center"));
+            game.set_entity_position(pet, mirror);
+        } else {
+            log.blocked(game, pet, &mirror);
+            log.stayed(pet, format_args!("its mirror tile at the room center is blocked"));
+        }
+    }
+}
+
+/// Doesn't move.
+pub struct Rest;
+
+impl Rest {
+    fn run<L: PetEvents>(&self, _game: &mut D15Game, pet: Entity, log: &mut L) {
+        log.stayed(pet, format_args!("the {} rests", name(pet)));
+    }
+}
+
+/// One pet's part of a `PetSchedule`: what it does in each phase, `phases[0]` in `Phase::ONE`.
+pub struct PetTurn {
+    pub pet: Entity,
+    pub phases: [&'static dyn PetBehaviour; 4],
+}
+
+/// What the pets of a boss room do after each move, in the order of `turns`. Each pet sees the
+/// board the ones before it left. Boards carry it like their `Rules`, see `D15Game::with_pets`.
+pub struct PetSchedule {
+    /// Tells schedules apart in `Debug` output, boards compare them by address.
+    pub name: &'static str,
+    pub turns: &'static [PetTurn],
+}
+
+impl PartialEq for PetSchedule {
+    fn eq(&self, other: &PetSchedule) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Eq for PetSchedule {}
+
+impl Hash for PetSchedule {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+    }
+}
+
+impl fmt::Debug for PetSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PetSchedule({})", self.name)
+    }
+}
+
+/// The D15 pets, what boards have unless they were given others with `D15Game::with_pets`. The
+/// only schedule `game::RULES_VERSION` and tablebases know about.
+pub static D15_PETS: PetSchedule = PetSchedule {
+    name: "D15",
+    turns: &[
+        PetTurn {
+            pet: DOG,
+            phases: [
+                &ColorJump { forward: 5, back: 2 },
+                &SwapWith { other: Entity::CAT },
+                &Slide { step_x: 0, step_y: 1, steps: 3 },
+                &SwapWith { other: DRAGON },
+            ],
+        },
+        PetTurn {
+            pet: Entity::CAT,
+            phases: [
+                &StepToward { target: DRAGON },
+                &Slide { step_x: 0, step_y: -1, steps: 3 },
+                &StepAway { from: PLAYER },
+                &Slide { step_x: 1, step_y: 0, steps: 3 },
+            ],
+        },
+        PetTurn {
+            pet: DRAGON,
+            phases: [
+                &MirrorBy { center: PLAYER },
+                &Rest,
+                &MirrorByRoomCenter,
+                &Slide { step_x: -1, step_y: 0, steps: 3 },
+            ],
+        },
+    ],
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::{SolveMode, Solver};
+
+    /// The D15 pets under another address, boards tell schedules apart by that.
+    static COPIED_PETS: PetSchedule = PetSchedule { name: "copy", turns: D15_PETS.turns };
+
+    #[test]
+    fn solves_with_other_pets() {
+        let game: D15Game = "60_4_3_5_3_5_3_3_3_5_4_A_B".parse().unwrap();
+        let mut solver = Solver::builder().mode(SolveMode::Exact).pets(&COPIED_PETS).build();
+        let solution = solver.solve_game(&game).unwrap();
+        assert_eq!((solution.hp, solution.cost), (48, 12));
+        assert_eq!(solution.moves, Solver::builder().mode(SolveMode::Exact).build().solve_game(&game).unwrap().moves);
+    }
+}
@@ -0,0 +1,287 @@
+//! Search settings by where the boss stands, see `StrategyProfiles`.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::ProfilesError;
+use crate::game::{D15Game, Entity, Move, PlayerState, Point, ROOM_MAX_Y};
+use crate::solver::{MoveOrdering, SolveMode, SolveOptions, Solver};
+
+/// How the search goes about boards with the boss at one position: the `besthp` a `Fast` search
+/// starts with, which moves get tried and in which order. A `Fast` search only finds lines that end
+/// above its cutoff, one too high leaves a board unsolved, one too low searches more than it has to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StrategyProfile {
+    /// Starts above this many hp get a cutoff `search_best_margin` below them and the search keeps
+    /// looking for better lines after the first one.
+    pub search_best_above: i16,
+    pub search_best_margin: i16,
+    /// Cutoff for starts above `high_above` hp up to `search_best_above`.
+    pub besthp_high: i16,
+    pub high_above: i16,
+    /// Cutoff for starts above `mid_above` hp up to `high_above`.
+    pub besthp_mid: i16,
+    pub mid_above: i16,
+    /// Cutoff for starts at `mid_above` hp or less.
+    pub besthp_low: i16,
+    pub ordering: MoveOrdering,
+    /// The search only tries PASSTURN below `passturn_window_end` hp down to
+    /// `passturn_window_start_low`, see `moves`. `passturn_window_start` only matters to
+    /// `MoveOrdering::Tuned`: with `late_pass` it tries PASSTURN last below it.
+    pub passturn_window_end: i16,
+    pub passturn_window_start: i16,
+    pub passturn_window_start_low: i16,
+}
+
+impl StrategyProfile {
+    pub const DEFAULT: StrategyProfile = StrategyProfile {
+        search_best_above: 159,
+        search_best_margin: 40,
+        besthp_high: 100,
+        high_above: 128,
+        besthp_mid: 47,
+        mid_above: 60,
+        besthp_low: 35,
+        ordering: MoveOrdering::Tuned { late_pass: false },
+        passturn_window_end: 140,
+        passturn_window_start: 120,
+        passturn_window_start_low: 80,
+    };
+
+    /// What the boss on x 5 always got: a higher cutoff and PASSTURN late.
+    pub const COLUMN_5: StrategyProfile = StrategyProfile {
+        besthp_mid: 54,
+        ordering: MoveOrdering::Tuned { late_pass: true },
+        ..StrategyProfile::DEFAULT
+    };
+
+    /// Cutoff for a start at `hp`.
+    pub fn besthp(&self, hp: i16) -> i16 {
+        if self.searches_best(hp) {
+            hp - self.search_best_margin
+        } else if hp > self.high_above {
+            self.besthp_high
+        } else if hp > self.mid_above {
+            self.besthp_mid
+        } else {
+            self.besthp_low
+        }
+    }
+
+    /// Whether a `Fast` search from `hp` keeps looking for better lines after the first one.
+    pub fn searches_best(&self, hp: i16) -> bool {
+        hp > self.search_best_above
+    }
+
+    /// The `D15Game::legal_moves` the search tries, in `ordering`. PASSTURN only from
+    /// `passturn_window_start_low` to below `passturn_window_end` hp: the game allows it at any hp,
+    /// but it costs too much to pay off outside of that.
+    pub fn moves(&self, game: &D15Game) -> Vec<Move> {
+        let mut moves = game.legal_moves();
+        if !(self.passturn_window_start_low..self.passturn_window_end).contains(&game.hp) {
+            moves.retain(|&move_oper| move_oper != Move::PASSTURN);
+        }
+        if let MoveOrdering::Tuned { late_pass } = self.ordering {
+            if moves.last() == Some(&Move::PASSTURN) && !(late_pass && game.hp < self.passturn_window_start) {
+                moves.pop();
+                moves.insert(0, Move::PASSTURN);
+            }
+        }
+        moves
+    }
+
+    /// `moves` of the board the search starts from.
+    pub fn start_moves(&self, game: &D15Game) -> Vec<Move> {
+        let mut possible_start_moves = self.moves(game);
+
+        if matches!(self.ordering, MoveOrdering::Tuned { .. }) && game.playerstate == PlayerState::SWORD {
+            let index_switch = possible_start_moves.iter().position(|x| *x == Move::SWITCH).unwrap();
+            possible_start_moves.remove(index_switch);
+            possible_start_moves.insert(0, Move::SWITCH);
+        }
+        possible_start_moves
+    }
+}
+
+impl Default for StrategyProfile {
+    fn default() -> StrategyProfile {
+        StrategyProfile::DEFAULT
+    }
+}
+
+/// `profile` for boards with the boss on `x`, `y`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PositionProfile {
+    pub x: i8,
+    pub y: i8,
+    pub profile: StrategyProfile,
+}
+
+/// A `StrategyProfile` for each boss position, what a `Solver` takes its cutoffs and move
+/// ordering from. The default has `StrategyProfile::COLUMN_5` for the boss anywhere on x 5 and
+/// `StrategyProfile::DEFAULT` everywhere else, `tune` derives better ones from a corpus of boards.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StrategyProfiles {
+    /// For boss positions not in `positions`.
+    pub default: StrategyProfile,
+    pub positions: Vec<PositionProfile>,
+}
+
+impl StrategyProfiles {
+    pub fn from_json(json: &str) -> Result<StrategyProfiles, ProfilesError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open(path: &std::path::Path) -> Result<StrategyProfiles, ProfilesError> {
+        StrategyProfiles::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("profiles are plain numbers")
+    }
+
+    pub fn get(&self, boss: &Point) -> &StrategyProfile {
+        self.positions.iter()
+            .find(|position| position.x == boss.x && position.y == boss.y)
+            .map_or(&self.default, |position| &position.profile)
+    }
+
+    /// Profile of where the boss stands on `game`, it never moves.
+    pub fn for_game(&self, game: &D15Game) -> &StrategyProfile {
+        self.get(game.get_entity_position(Entity::BOSS))
+    }
+}
+
+impl Default for StrategyProfiles {
+    fn default() -> StrategyProfiles {
+        StrategyProfiles {
+            default: StrategyProfile::DEFAULT,
+            positions: (0..=ROOM_MAX_Y).map(|y| PositionProfile { x: 5, y, profile: StrategyProfile::COLUMN_5 }).collect(),
+        }
+    }
+}
+
+/// Orderings `tune` tries.
+const ORDERINGS: [MoveOrdering; 3] = [MoveOrdering::Tuned { late_pass: false }, MoveOrdering::Tuned { late_pass: true }, MoveOrdering::Legal];
+
+/// `base` with a profile of its own for each boss position of `corpus`, the other positions keep
+/// theirs.
+///
+/// Each board of a position is solved `Exact` first. A cutoff of a position goes 1 below the worst
+/// of the best lines of its boards in that hp range, the highest one that still solves all of
+/// them, or stays `base`'s without any. Above `search_best_above` that's the margin below the start
+/// instead. The hp ranges stay `base`'s. With those cutoffs each ordering solves the boards `Fast`,
+/// the one that expands the fewest boards wins. `options` are what the solves run with, e.g. rules,
+/// a budget or threads. An exact solve the budget cut short still gives a line to stay below, a
+/// board without any line is left out, and so is a position with none left.
+pub fn tune(corpus: &[D15Game], base: &StrategyProfiles, options: &SolveOptions) -> StrategyProfiles {
+    let mut by_position: BTreeMap<(i8, i8), Vec<&D15Game>> = BTreeMap::new();
+    for game in corpus {
+        let boss = game.get_entity_position(Entity::BOSS);
+        by_position.entry((boss.x, boss.y)).or_default().push(game);
+    }
+
+    let tuned: Vec<PositionProfile> = by_position.into_iter().filter_map(|((x, y), games)| {
+        let default = base.get(&Point { x, y });
+        let exact = SolveOptions { mode: SolveMode::Exact, ..options.clone() };
+        let best: Vec<(&D15Game, i16)> = games.into_iter().filter_map(|game| {
+            let mut solver = Solver::with_options(&exact);
+            solver.do_solve(game);
+            solver.solution().map(|solution| (game, solution.hp))
+        }).collect();
+        if best.is_empty() {
+            return None;
+        }
+
+        let cutoff = |range: std::ops::RangeInclusive<i16>, otherwise: i16| {
+            best.iter().filter(|(game, _)| range.contains(&game.hp)).map(|&(_, hp)| hp - 1).min().unwrap_or(otherwise)
+        };
+        let margin = best.iter()
+            .filter(|(game, _)| default.searches_best(game.hp))
+            .map(|&(game, hp)| game.hp - hp + 1)
+            .max()
+            .unwrap_or(default.search_best_margin);
+        let profile = StrategyProfile {
+            search_best_margin: margin,
+            besthp_high: cutoff(default.high_above + 1..=default.search_best_above, default.besthp_high),
+            besthp_mid: cutoff(default.mid_above + 1..=default.high_above, default.besthp_mid),
+            besthp_low: cutoff(i16::MIN..=default.mid_above, default.besthp_low),
+            ..*default
+        };
+
+        // the base's ordering first, it stays on a tie
+        let orderings = std::iter::once(default.ordering).chain(ORDERINGS.iter().copied().filter(|&ordering| ordering != default.ordering));
+        let ordering = orderings.min_by_key(|&ordering| {
+            let candidate = StrategyProfile { ordering, ..profile };
+            let profiles = Arc::new(StrategyProfiles { default: candidate, positions: vec![] });
+            let fast = SolveOptions { mode: SolveMode::Fast, profiles: Some(profiles), ordering: None, ..options.clone() };
+            best.iter().fold((0, 0), |(unsolved, nodes), (game, _)| {
+                let mut solver = Solver::with_options(&fast);
+                solver.do_solve(game);
+                (unsolved + solver.solution().is_none() as usize, nodes + solver.stats().nodes)
+            })
+        }).unwrap_or(default.ordering);
+
+        Some(PositionProfile { x, y, profile: StrategyProfile { ordering, ..profile } })
+    }).collect();
+
+    let mut positions: Vec<PositionProfile> = base.positions.iter().copied()
+        .filter(|position| !tuned.iter().any(|each| each.x == position.x && each.y == position.y))
+        .collect();
+    positions.extend(tuned);
+    positions.sort_by_key(|position| (position.x, position.y));
+    StrategyProfiles { default: base.default, positions }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_round_trips_and_fills_in_defaults() {
+        let profiles = StrategyProfiles::default();
+        assert_eq!(StrategyProfiles::from_json(&profiles.to_json()).unwrap(), profiles);
+
+        let partial = StrategyProfiles::from_json(r#"{ "positions": [{ "x": 2, "y": 3, "profile": { "besthp_low": 30, "ordering": "Legal" } }] }"#).unwrap();
+        assert_eq!(partial.default, StrategyProfile::DEFAULT);
+        assert_eq!(*partial.get(&Point { x: 2, y: 3 }), StrategyProfile { besthp_low: 30, ordering: MoveOrdering::Legal, ..StrategyProfile::DEFAULT });
+        assert_eq!(*partial.get(&Point { x: 5, y: 3 }), StrategyProfile::DEFAULT);
+
+        assert!(matches!(StrategyProfiles::from_json(r#"{ "default": { "besthp": 30 } }"#), Err(ProfilesError::Json(_))));
+    }
+
+    #[test]
+    fn default_keeps_column_5_apart() {
+        let profiles = StrategyProfiles::default();
+        assert_eq!(*profiles.get(&Point { x: 5, y: 0 }), StrategyProfile::COLUMN_5);
+        assert_eq!(*profiles.get(&Point { x: 4, y: 3 }), StrategyProfile::DEFAULT);
+        assert_eq!([StrategyProfile::DEFAULT.besthp(200), StrategyProfile::DEFAULT.besthp(150), StrategyProfile::DEFAULT.besthp(100), StrategyProfile::DEFAULT.besthp(60)], [160, 100, 47, 35]);
+    }
+
+    #[test]
+    fn tune_cuts_off_just_below_the_worst_best_line() {
+        let corpus: Vec<D15Game> = ["60_4_3_5_3_5_3_3_3_5_4_A_B", "56_4_3_4_2_5_3_3_3_5_4_A_R"].iter().map(|magic_string| magic_string.parse().unwrap()).collect();
+        let base = StrategyProfiles::default();
+        let tuned = tune(&corpus, &base, &SolveOptions::default());
+
+        let best: Vec<i16> = corpus.iter().map(|game| {
+            let mut solver = Solver::builder().mode(SolveMode::Exact).build();
+            solver.solve_game(game).unwrap().hp
+        }).collect();
+        let profile = tuned.get(&Point { x: 4, y: 3 });
+        assert_eq!(profile.besthp_low, best.iter().min().unwrap() - 1);
+        assert_eq!((profile.besthp_mid, profile.besthp_high, profile.search_best_margin), (StrategyProfile::DEFAULT.besthp_mid, StrategyProfile::DEFAULT.besthp_high, StrategyProfile::DEFAULT.search_best_margin));
+        assert_eq!(tuned.positions.len(), base.positions.len() + 1, "the other positions stay");
+
+        let mut fast = Solver::builder().profiles(Arc::new(tuned)).build();
+        for game in &corpus {
+            assert!(fast.solve_game(game).is_ok(), "{:?}", game);
+        }
+    }
+}
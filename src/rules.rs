@@ -0,0 +1,216 @@
+//! The numbers of the rules, see `Rules`.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::RulesError;
+use crate::game::{Phase, ROOM_MAX_X, ROOM_MAX_Y};
+
+/// Every number the rules depend on: when the pets change phase, what moves cost and how far the
+/// player steps. The default is the game as it is, a patched one can be loaded from a JSON or TOML
+/// file instead of building the solver again. Fields missing from the file keep their default.
+///
+/// Boards carry their rules as `&'static Rules`, copying a board must stay as cheap as it is, so
+/// loaded rules get leaked once, see `Rules::leak`. What the pets do in each phase is code and
+/// covered by `game::RULES_VERSION` instead, and how the search goes about the game, e.g. when it
+/// tries PASSTURN, by `StrategyProfile`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rules {
+    /// Phase 1 lasts while the hp is above the first, phase 2 above the second, phase 3 above the
+    /// third, phase 4 after that. Highest first.
+    pub phase_limits: [i16; 3],
+    /// Hp a move costs holding the sword.
+    pub sword_damage: i16,
+    /// Hp a move costs in armor.
+    pub armor_damage: i16,
+    /// Hp a PASSTURN costs on top of the move.
+    pub passturn_damage: i16,
+    /// Tiles the player steps holding the sword.
+    pub sword_step: i8,
+    /// Tiles the player steps in armor.
+    pub armor_step: i8,
+    /// Hp a magic string may start with.
+    pub start_hp_min: i16,
+    pub start_hp_max: i16,
+}
+
+/// Rules of boards that weren't given others with `D15Game::with_rules`.
+pub static DEFAULT_RULES: Rules = Rules::DEFAULT;
+
+impl Rules {
+    pub const DEFAULT: Rules = Rules {
+        phase_limits: [130, 100, 60],
+        sword_damage: 4,
+        armor_damage: 2,
+        passturn_damage: 25,
+        sword_step: 2,
+        armor_step: 1,
+        start_hp_min: 50,
+        start_hp_max: 200,
+    };
+
+    pub fn from_json(json: &str) -> Result<Rules, RulesError> {
+        let rules: Rules = serde_json::from_str(json)?;
+        rules.check()?;
+        Ok(rules)
+    }
+
+    pub fn from_toml(toml: &str) -> Result<Rules, RulesError> {
+        let rules: Rules = toml::from_str(toml)?;
+        rules.check()?;
+        Ok(rules)
+    }
+
+    /// Reads a rules file, TOML if it ends in `.toml`, JSON otherwise.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open(path: &std::path::Path) -> Result<Rules, RulesError> {
+        let text = std::fs::read_to_string(path)?;
+        if path.extension().is_some_and(|extension| extension == "toml") {
+            Rules::from_toml(&text)
+        } else {
+            Rules::from_json(&text)
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("rules are plain numbers")
+    }
+
+    /// Rules to give boards, they have to outlive every board. Meant for once per loaded file.
+    pub fn leak(self) -> &'static Rules {
+        Box::leak(Box::new(self))
+    }
+
+    /// Refuses numbers the search can't work with, e.g. a move that costs no hp never ends.
+    pub fn check(&self) -> Result<(), RulesError> {
+        let [limit_1, limit_2, limit_3] = self.phase_limits;
+        if !(limit_1 > limit_2 && limit_2 > limit_3) {
+            return Err(RulesError::Invalid("phase_limits must be highest first"));
+        }
+        if self.sword_damage <= 0 || self.armor_damage <= 0 || self.passturn_damage < 0 {
+            return Err(RulesError::Invalid("every move has to cost hp"));
+        }
+        if !(1..=ROOM_MAX_X.min(ROOM_MAX_Y)).contains(&self.sword_step) || !(1..=ROOM_MAX_X.min(ROOM_MAX_Y)).contains(&self.armor_step) {
+            return Err(RulesError::Invalid("steps must be at least 1 and fit the room"));
+        }
+        if self.start_hp_min > self.start_hp_max {
+            return Err(RulesError::Invalid("start_hp_min is above start_hp_max"));
+        }
+        Ok(())
+    }
+
+    /// Phase the pets answer a move at `hp` in.
+    pub fn phase(&self, hp: i16) -> Phase {
+        let [limit_1, limit_2, limit_3] = self.phase_limits;
+        if hp > limit_1 {
+            Phase::ONE
+        } else if hp > limit_2 {
+            Phase::TWO
+        } else if hp > limit_3 {
+            Phase::THREE
+        } else {
+            Phase::FOUR
+        }
+    }
+
+    /// `phase` lasts while the hp is above this, `None` for the last one.
+    pub fn phase_limit(&self, phase: Phase) -> Option<i16> {
+        match phase {
+            Phase::ONE => Some(self.phase_limits[0]),
+            Phase::TWO => Some(self.phase_limits[1]),
+            Phase::THREE => Some(self.phase_limits[2]),
+            Phase::FOUR => None,
+        }
+    }
+
+    /// Least hp any single move can cost.
+    pub fn min_move_damage(&self) -> i16 {
+        self.sword_damage.min(self.armor_damage)
+    }
+
+    /// The numbers a tablebase is only good for, written into its header. The start hp range isn't
+    /// among them, it doesn't change what any board is worth.
+    pub(crate) fn constants(&self) -> [i16; 8] {
+        let [limit_1, limit_2, limit_3] = self.phase_limits;
+        [
+            limit_1, limit_2, limit_3,
+            self.sword_damage, self.armor_damage, self.passturn_damage,
+            self.sword_step as i16, self.armor_step as i16,
+        ]
+    }
+
+    /// Rules from a tablebase header, see `constants`.
+    pub(crate) fn from_constants(constants: [i16; 8]) -> Rules {
+        let [limit_1, limit_2, limit_3, sword_damage, armor_damage, passturn_damage, sword_step, armor_step] = constants;
+        Rules {
+            phase_limits: [limit_1, limit_2, limit_3],
+            sword_damage,
+            armor_damage,
+            passturn_damage,
+            sword_step: sword_step as i8,
+            armor_step: armor_step as i8,
+            ..Rules::DEFAULT
+        }
+    }
+}
+
+impl Default for Rules {
+    fn default() -> Rules {
+        Rules::DEFAULT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_fields_keep_their_default() {
+        let rules = Rules::from_json(r#"{ "sword_damage": 5, "phase_limits": [140, 100, 50] }"#).unwrap();
+        assert_eq!(rules, Rules { sword_damage: 5, phase_limits: [140, 100, 50], ..Rules::DEFAULT });
+        assert_eq!(Rules::from_json("{}").unwrap(), Rules::DEFAULT);
+    }
+
+    #[test]
+    fn reads_toml_like_json() {
+        let toml = "sword_damage = 5\narmor_step = 2\nphase_limits = [140, 100, 50]\n";
+        let json = r#"{ "sword_damage": 5, "armor_step": 2, "phase_limits": [140, 100, 50] }"#;
+        assert_eq!(Rules::from_toml(toml).unwrap(), Rules::from_json(json).unwrap());
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let rules = Rules { passturn_damage: 20, start_hp_max: 180, ..Rules::DEFAULT };
+        assert_eq!(Rules::from_json(&rules.to_json()).unwrap(), rules);
+    }
+
+    #[test]
+    fn refuses_unknown_fields() {
+        assert!(matches!(Rules::from_json(r#"{ "sword_damage": 5, "typo": 1 }"#), Err(RulesError::Json(_))));
+        assert!(matches!(Rules::from_toml("passturn_window_end = 140"), Err(RulesError::Toml(_))));
+    }
+
+    #[test]
+    fn check_refuses_rules_the_search_cannot_work_with() {
+        let invalid = [
+            Rules { phase_limits: [100, 130, 60], ..Rules::DEFAULT },
+            Rules { sword_damage: 0, ..Rules::DEFAULT },
+            Rules { passturn_damage: -1, ..Rules::DEFAULT },
+            Rules { armor_step: 0, ..Rules::DEFAULT },
+            Rules { sword_step: ROOM_MAX_X + 1, ..Rules::DEFAULT },
+            Rules { start_hp_min: 201, ..Rules::DEFAULT },
+        ];
+        for rules in &invalid {
+            assert!(matches!(rules.check(), Err(RulesError::Invalid(_))), "{:?}", rules);
+        }
+        assert!(Rules::DEFAULT.check().is_ok());
+        assert!(Rules::from_json(r#"{ "armor_damage": 0 }"#).is_err());
+    }
+
+    #[test]
+    fn phases_change_below_their_limits() {
+        let rules = Rules::DEFAULT;
+        assert_eq!([rules.phase(131), rules.phase(130), rules.phase(101), rules.phase(100), rules.phase(60)], [Phase::ONE, Phase::TWO, Phase::TWO, Phase::THREE, Phase::FOUR]);
+        assert_eq!(Rules::from_constants(rules.constants()), rules);
+    }
+}
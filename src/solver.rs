@@ -0,0 +1,931 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use hash_hasher::HashBuildHasher;
+use serde::{Deserialize, Serialize};
+
+use wasm_bindgen::prelude::*;
+
+use crate::astar::{astar_k_best, astar_search};
+use crate::budget::{BudgetTracker, SolveBudget, StopReason};
+use crate::error::SolveError;
+use crate::game::{Color, D15Game, Entity, Move, Phase, PlayerState, Point, StateKey, ENTITIES};
+use crate::parallel::parallel_search;
+use crate::pets::PetSchedule;
+use crate::profiles::{StrategyProfile, StrategyProfiles};
+use crate::rules::Rules;
+use crate::stats::{SearchTrace, SolveStats, TraceEvent};
+use crate::tablebase::Tablebase;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SolveMode {
+    /// Hand-tuned hp cutoffs, returns the first line that clears them.
+    Fast,
+    /// Exhausts the tree and returns the line with the most remaining hp.
+    Exact,
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SearchStrategy {
+    /// Brute-force depth-first search pruned by `besthp`.
+    DepthFirst,
+    /// Best-first search on spent hp, see `astar::astar_search`.
+    AStar,
+}
+
+/// Outweighs all the hp a line can spend, so one move more or less decides before any hp does. Hp
+/// and death hp are both `i16`, no line spends 2^16 hp whatever the rules and death hp are.
+const TIEBREAK_WEIGHT: u32 = 1 << 16;
+
+/// What the solver minimizes. Every move is charged a cost, the best line is the cheapest one.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SolveObjective {
+    /// Most remaining hp.
+    MaxHp,
+    /// Fewest moves, ties go to the line with more hp.
+    FewestMoves,
+    /// Fewest `SWITCH` and `PASSTURN` moves, ties go to the line with more hp.
+    FewestRiskyMoves,
+    /// Spent hp, moves and risky moves, each times its weight.
+    Weighted { hp: u32, moves: u32, risky: u32 },
+}
+
+impl SolveObjective {
+    fn weights(&self) -> (u32, u32, u32) {
+        match *self {
+            SolveObjective::MaxHp => (1, 0, 0),
+            SolveObjective::FewestMoves => (1, TIEBREAK_WEIGHT, 0),
+            SolveObjective::FewestRiskyMoves => (1, 0, TIEBREAK_WEIGHT),
+            SolveObjective::Weighted { hp, moves, risky } => (hp, moves, risky),
+        }
+    }
+
+    /// Saturates rather than wrapping, so even the largest weights keep cheaper lines cheaper.
+    pub fn move_cost(&self, move_oper: Move, hp_spent: i16) -> u64 {
+        let (hp, moves, risky) = self.weights();
+        let is_risky = move_oper == Move::SWITCH || move_oper == Move::PASSTURN;
+        (hp as u64 * hp_spent as u64).saturating_add(moves as u64).saturating_add(if is_risky { risky as u64 } else { 0 })
+    }
+
+    /// Least any move can cost under `rules`, what each move still needed adds to a lower bound.
+    pub fn min_move_cost(&self, rules: &Rules) -> u64 {
+        let (hp, moves, _) = self.weights();
+        (hp as u64 * rules.min_move_damage() as u64).saturating_add(moves as u64)
+    }
+}
+
+/// In which order the search tries the `D15Game::legal_moves` of a board, see
+/// `StrategyProfile::moves`. It tries the same ones either way, so the order only decides how soon
+/// good lines turn up and which of equally good ones is returned. Comes from the boss position's
+/// `StrategyProfile` unless given.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum MoveOrdering {
+    /// PASSTURN first, with `late_pass` last below `StrategyProfile::passturn_window_start` hp.
+    /// `SWITCH` first of the start moves when holding the sword.
+    Tuned { late_pass: bool },
+    /// `legal_moves` in the order they come.
+    Legal,
+}
+
+/// Everything that shapes a search, handed to `Solver::with_options`.
+#[derive(Clone)]
+pub struct SolveOptions {
+    pub mode: SolveMode,
+    pub strategy: SearchStrategy,
+    pub objective: SolveObjective,
+    /// Replaces the ordering of the boss position's profile, see `StrategyProfile::ordering`.
+    pub ordering: Option<MoveOrdering>,
+    pub budget: SolveBudget,
+    /// Threads the depth-first search spreads its start moves over, see `parallel::parallel_search`.
+    pub threads: usize,
+    /// Answers `MaxHp` searches for boards it covers without searching, see `Tablebase`.
+    pub tablebase: Option<Arc<Tablebase>>,
+    /// Gets what the search does as JSON lines, see `SearchTrace`.
+    pub trace: Option<SearchTrace>,
+    /// Replaces the death hp of the boards that get solved, see `D15Game::with_death_hp`.
+    pub death_hp: Option<i16>,
+    /// Replaces the rules of the boards that get solved, see `D15Game::with_rules`. The
+    /// `solve_magicstr*` functions parse with them too.
+    pub rules: Option<&'static Rules>,
+    /// Replaces the pets of the boards that get solved, see `D15Game::with_pets`.
+    pub pets: Option<&'static PetSchedule>,
+    /// Cutoffs and move ordering by boss position, `StrategyProfiles::default()` if not given.
+    pub profiles: Option<Arc<StrategyProfiles>>,
+}
+
+impl Default for SolveOptions {
+    fn default() -> SolveOptions {
+        SolveOptions {
+            mode: SolveMode::Fast,
+            strategy: SearchStrategy::DepthFirst,
+            objective: SolveObjective::MaxHp,
+            ordering: None,
+            budget: SolveBudget::default(),
+            threads: 1,
+            tablebase: None,
+            trace: None,
+            death_hp: None,
+            rules: None,
+            pets: None,
+            profiles: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Solution {
+    pub moves: Vec<Move>,
+    /// Hp left after the last move.
+    pub hp: i16,
+    /// What the line costs under the objective it was searched with.
+    pub cost: u64,
+    /// The board after each of `moves`, the last one is the won board.
+    pub steps: Vec<Step>,
+}
+
+impl Solution {
+    /// `moves` played from `start`, the board they were found for.
+    pub fn new(start: &D15Game, moves: Vec<Move>, cost: u64) -> Solution {
+        let mut game = start.clone();
+        let steps: Vec<Step> = moves.iter().map(|&move_oper| {
+            game.do_move(&move_oper);
+            Step::after(move_oper, &game)
+        }).collect();
+        Solution { moves, hp: game.hp, cost, steps }
+    }
+}
+
+/// A move of a `Solution` and the board right after it, pets moved already.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    pub move_oper: Move,
+    /// Where each piece stands, in `game::ENTITIES` order, see `position`.
+    pub positions: [Point; 5],
+    pub hp: i16,
+    pub playerstate: PlayerState,
+    /// The next pet pattern.
+    pub color: Color,
+    /// Phase the pets answer the next move in, see `D15Game::phase`.
+    pub phase: Phase,
+}
+
+impl Step {
+    pub(crate) fn after(move_oper: Move, game: &D15Game) -> Step {
+        Step {
+            move_oper,
+            positions: ENTITIES.map(|entity| game.get_entity_position(entity).clone()),
+            hp: game.hp,
+            playerstate: game.playerstate,
+            color: game.color(),
+            phase: game.phase(),
+        }
+    }
+
+    pub fn position(&self, entity: Entity) -> &Point {
+        &self.positions[ENTITIES.iter().position(|&each| each == entity).unwrap()]
+    }
+
+    /// The board to go on from, with the default `game::DEATH_HP`, rules and pets.
+    pub fn game(&self) -> D15Game {
+        let [player, boss, cat, dog, dragon] = self.positions.clone();
+        D15Game::new(self.hp, boss, player, cat, dog, dragon, self.playerstate, self.color)
+    }
+}
+
+/// Sets up a `Solver` one option at a time, everything not set is the `SolveOptions` default:
+///
+/// ```no_run
+/// use ed15r::{SolveMode, Solver, SolveObjective};
+///
+/// let game = "60_4_3_4_4_5_3_3_3_5_4_A_Y".parse().unwrap();
+/// let mut solver = Solver::builder().mode(SolveMode::Exact).objective(SolveObjective::FewestMoves).build();
+/// let solution = solver.solve_game(&game).unwrap();
+/// assert_eq!(solution.steps.last().map(|step| step.game().check_win_2()), Some(true));
+/// ```
+#[derive(Clone, Default)]
+pub struct SolverBuilder {
+    options: SolveOptions,
+    alternatives: Option<(usize, usize)>,
+}
+
+impl SolverBuilder {
+    pub fn mode(mut self, mode: SolveMode) -> SolverBuilder {
+        self.options.mode = mode;
+        self
+    }
+
+    pub fn strategy(mut self, strategy: SearchStrategy) -> SolverBuilder {
+        self.options.strategy = strategy;
+        self
+    }
+
+    pub fn objective(mut self, objective: SolveObjective) -> SolverBuilder {
+        self.options.objective = objective;
+        self
+    }
+
+    pub fn ordering(mut self, ordering: MoveOrdering) -> SolverBuilder {
+        self.options.ordering = Some(ordering);
+        self
+    }
+
+    pub fn budget(mut self, budget: SolveBudget) -> SolverBuilder {
+        self.options.budget = budget;
+        self
+    }
+
+    /// See `SolveOptions::threads`.
+    pub fn threads(mut self, threads: usize) -> SolverBuilder {
+        self.options.threads = threads;
+        self
+    }
+
+    pub fn tablebase(mut self, tablebase: Arc<Tablebase>) -> SolverBuilder {
+        self.options.tablebase = Some(tablebase);
+        self
+    }
+
+    pub fn trace(mut self, trace: SearchTrace) -> SolverBuilder {
+        self.options.trace = Some(trace);
+        self
+    }
+
+    pub fn death_hp(mut self, death_hp: i16) -> SolverBuilder {
+        self.options.death_hp = Some(death_hp);
+        self
+    }
+
+    pub fn rules(mut self, rules: &'static Rules) -> SolverBuilder {
+        self.options.rules = Some(rules);
+        self
+    }
+
+    pub fn pets(mut self, pets: &'static PetSchedule) -> SolverBuilder {
+        self.options.pets = Some(pets);
+        self
+    }
+
+    pub fn profiles(mut self, profiles: Arc<StrategyProfiles>) -> SolverBuilder {
+        self.options.profiles = Some(profiles);
+        self
+    }
+
+    /// See `Solver::set_alternatives`.
+    pub fn alternatives(mut self, k: usize, min_difference: usize) -> SolverBuilder {
+        self.alternatives = Some((k, min_difference));
+        self
+    }
+
+    pub fn options(&self) -> &SolveOptions {
+        &self.options
+    }
+
+    pub fn build(&self) -> Solver {
+        let mut solver = Solver::with_options(&self.options);
+        if let Some((k, min_difference)) = self.alternatives {
+            solver.set_alternatives(k, min_difference);
+        }
+        solver
+    }
+}
+
+/// A line better than every line the search found before it, see `Solver::set_on_improvement`.
+pub struct Improvement<'a> {
+    pub solution: &'a Solution,
+    /// Boards expanded until it was found.
+    pub nodes: u64,
+}
+
+type ImprovementCallback = Box<dyn FnMut(&Improvement)>;
+
+pub struct Solver {
+    mode: SolveMode,
+    strategy: SearchStrategy,
+    objective: SolveObjective,
+    ordering: Option<MoveOrdering>,
+    profiles: Option<Arc<StrategyProfiles>>,
+    /// Of the board the last `do_solve` started from, with `ordering` if given.
+    profile: StrategyProfile,
+    besthp: i16,
+    solve: Option<Solution>,
+    alternatives: Vec<Solution>,
+    k_best: usize,
+    min_difference: usize,
+    budget: SolveBudget,
+    tracker: BudgetTracker,
+    /// Cost each board was reached at. The hp is part of the key, a board with more hp is another
+    /// board and doesn't stand in for this one: the pets change their rules at hp thresholds, so
+    /// more hp can mean worse timing.
+    checked_perms: HashMap<StateKey, u64, HashBuildHasher>,
+    search_best: bool,
+    threads: usize,
+    tablebase: Option<Arc<Tablebase>>,
+    /// Whether the tablebase had the board, its answer is the exact one.
+    from_tablebase: bool,
+    /// Board the last `do_solve` started from, what lines get played from.
+    start: Option<D15Game>,
+    death_hp: Option<i16>,
+    rules: Option<&'static Rules>,
+    pets: Option<&'static PetSchedule>,
+    on_improvement: Option<ImprovementCallback>,
+    stats: SolveStats,
+    trace: Option<SearchTrace>,
+    /// Searches over the whole tree so far, `Exact` does a second one after the fast one.
+    passes: u32,
+}
+impl Solver {
+    pub fn new(mode: SolveMode, strategy: SearchStrategy) -> Solver {
+        Solver {
+            mode,
+            strategy,
+            objective: SolveObjective::MaxHp,
+            ordering: None,
+            profiles: None,
+            profile: StrategyProfile::DEFAULT,
+            solve: None,
+            alternatives: vec![],
+            k_best: 1,
+            min_difference: 0,
+            budget: SolveBudget::default(),
+            tracker: BudgetTracker::new(SolveBudget::default()),
+            besthp: 0,
+            checked_perms: HashMap::with_capacity_and_hasher(1000000, HashBuildHasher::default()),
+            search_best: false,
+            threads: 1,
+            tablebase: None,
+            from_tablebase: false,
+            start: None,
+            death_hp: None,
+            rules: None,
+            pets: None,
+            on_improvement: None,
+            stats: SolveStats::default(),
+            trace: None,
+            passes: 0,
+        }
+    }
+
+    pub fn builder() -> SolverBuilder {
+        SolverBuilder::default()
+    }
+
+    pub fn with_options(options: &SolveOptions) -> Solver {
+        let mut solver = Solver::new(options.mode, options.strategy);
+        solver.set_objective(options.objective);
+        solver.set_ordering(options.ordering);
+        solver.set_budget(options.budget.clone());
+        solver.set_threads(options.threads);
+        solver.set_tablebase(options.tablebase.clone());
+        solver.set_trace(options.trace.clone());
+        solver.set_death_hp(options.death_hp);
+        solver.set_rules(options.rules);
+        solver.set_pets(options.pets);
+        solver.set_profiles(options.profiles.clone());
+        solver
+    }
+
+    pub fn set_objective(&mut self, objective: SolveObjective) {
+        self.objective = objective;
+    }
+
+    /// See `SolveOptions::ordering`.
+    pub fn set_ordering(&mut self, ordering: Option<MoveOrdering>) {
+        self.ordering = ordering;
+    }
+
+    pub fn set_budget(&mut self, budget: SolveBudget) {
+        self.budget = budget;
+    }
+
+    /// Only the depth-first search uses more than one thread. wasm32 has no threads, there it's
+    /// always one.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = if cfg!(target_arch = "wasm32") { 1 } else { threads.max(1) };
+    }
+
+    pub fn set_tablebase(&mut self, tablebase: Option<Arc<Tablebase>>) {
+        self.tablebase = tablebase;
+    }
+
+    /// See `SolveOptions::death_hp`.
+    pub fn set_death_hp(&mut self, death_hp: Option<i16>) {
+        self.death_hp = death_hp;
+    }
+
+    /// See `SolveOptions::rules`.
+    pub fn set_rules(&mut self, rules: Option<&'static Rules>) {
+        self.rules = rules;
+    }
+
+    /// See `SolveOptions::pets`.
+    pub fn set_pets(&mut self, pets: Option<&'static PetSchedule>) {
+        self.pets = pets;
+    }
+
+    /// See `SolveOptions::profiles`.
+    pub fn set_profiles(&mut self, profiles: Option<Arc<StrategyProfiles>>) {
+        self.profiles = profiles;
+    }
+
+    pub fn set_trace(&mut self, trace: Option<SearchTrace>) {
+        self.trace = trace;
+    }
+
+    fn trace(&self, event: TraceEvent) {
+        if let Some(trace) = &self.trace {
+            trace.record(&event);
+        }
+    }
+
+    /// Asks for up to `k` lines ranked by remaining hp instead of a single one, each differing from
+    /// the better ones in at least `min_difference` moves. See `astar::astar_k_best`.
+    pub fn set_alternatives(&mut self, k: usize, min_difference: usize) {
+        self.k_best = k;
+        self.min_difference = min_difference;
+    }
+
+    /// Called with every line that improves on the ones before while the search is still running.
+    /// The depth-first search keeps refining its line when `search_best` is set, the queued
+    /// strategies find their best line first and report just that one.
+    pub fn set_on_improvement<F: FnMut(&Improvement) + 'static>(&mut self, on_improvement: F) {
+        self.on_improvement = Some(Box::new(on_improvement));
+    }
+
+    fn improved(&mut self) {
+        if let Some(solution) = self.solve.as_ref() {
+            let nodes = self.tracker.nodes();
+            if let Some(trace) = &self.trace {
+                trace.record(&TraceEvent::Improvement { moves: &solution.moves, hp: solution.hp, cost: solution.cost, nodes });
+            }
+            if let Some(on_improvement) = self.on_improvement.as_mut() {
+                on_improvement(&Improvement { solution, nodes });
+            }
+        }
+    }
+
+    pub fn solution(&self) -> Option<&Solution> {
+        self.solve.as_ref()
+    }
+
+    /// `solution()`, or why there is none.
+    pub fn result(&self) -> Result<&Solution, SolveError> {
+        match (&self.solve, self.stop_reason()) {
+            (Some(solution), _) => Ok(solution),
+            (None, Some(reason)) => Err(SolveError::OutOfBudget { reason, stats: self.stats }),
+            (None, None) => Err(SolveError::Unsolvable { stats: self.stats }),
+        }
+    }
+
+    /// `do_solve` and its `result()`.
+    pub fn solve_game(&mut self, game: &D15Game) -> Result<&Solution, SolveError> {
+        self.do_solve(game);
+        self.result()
+    }
+
+    /// All lines found by a search with `set_alternatives`, best first.
+    pub fn alternatives(&self) -> &[Solution] {
+        &self.alternatives
+    }
+
+    /// Whether no line cheaper than `solution()` under the objective exists among the
+    /// `D15Game::legal_moves`. An `Exact` search that ran to the end proves that, and so does any
+    /// line `AStar` finds in `Exact` mode since it comes off the queue in cost order, or a line
+    /// from the tablebase. A `Fast` search never does, its `besthp` cutoff leaves out the lines
+    /// below it and one of those can be cheaper.
+    pub fn proven_optimal(&self) -> bool {
+        let cut_off = self.mode == SolveMode::Fast && !self.from_tablebase;
+        let queued = self.strategy == SearchStrategy::AStar || self.ranks_alternatives() || self.from_tablebase;
+        !cut_off && ((queued && self.solve.is_some()) || (self.mode == SolveMode::Exact && self.complete()))
+    }
+
+    /// False if the budget ran out before the search did.
+    pub fn complete(&self) -> bool {
+        self.tracker.stopped().is_none()
+    }
+
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.tracker.stopped()
+    }
+
+    /// What the last `do_solve` did, whether it found a line or not.
+    pub fn stats(&self) -> &SolveStats {
+        &self.stats
+    }
+
+    fn ranks_alternatives(&self) -> bool {
+        self.k_best > 1 || self.min_difference > 0
+    }
+
+    fn init_besthp(&mut self, game: &D15Game) {
+        self.search_best = self.profile.searches_best(game.hp);
+        self.besthp = self.profile.besthp(game.hp);
+    }
+
+    pub fn do_solve(&mut self, game: &D15Game) {
+        // nothing of a solve before may bound this one
+        self.solve = None;
+        self.alternatives.clear();
+        self.besthp = 0;
+        self.search_best = false;
+        self.checked_perms.clear();
+        self.from_tablebase = false;
+
+        let adjusted;
+        let game = if self.death_hp.is_some() || self.rules.is_some() || self.pets.is_some() {
+            let mut start = game.clone();
+            if let Some(death_hp) = self.death_hp {
+                start = start.with_death_hp(death_hp);
+            }
+            if let Some(rules) = self.rules {
+                start = start.with_rules(rules);
+            }
+            if let Some(pets) = self.pets {
+                start = start.with_pets(pets);
+            }
+            adjusted = start;
+            &adjusted
+        } else {
+            game
+        };
+
+        self.profile = match &self.profiles {
+            Some(profiles) => *profiles.for_game(game),
+            None => *StrategyProfiles::default().for_game(game),
+        };
+        if let Some(ordering) = self.ordering {
+            self.profile.ordering = ordering;
+        }
+        self.tracker = BudgetTracker::new(self.budget.clone());
+        self.stats = SolveStats::default();
+        self.passes = 0;
+        self.start = Some(game.clone());
+        self.trace(TraceEvent::Solve { hp: game.hp, mode: &format!("{:?}", self.mode), strategy: &format!("{:?}", self.strategy), threads: self.threads });
+
+        self.run(game);
+
+        self.stats.nodes = self.tracker.nodes();
+        self.stats.elapsed = self.tracker.elapsed();
+        self.stats.peak_table_size = self.stats.peak_table_size.max(self.checked_perms.len());
+        self.trace(TraceEvent::Done { hp: self.solve.as_ref().map(|solution| solution.hp), stopped: self.tracker.stopped(), stats: &self.stats });
+    }
+
+    fn run(&mut self, game: &D15Game) {
+        if self.objective == SolveObjective::MaxHp && !self.ranks_alternatives() {
+            if let Some(solved) = self.tablebase.as_ref().and_then(|tablebase| tablebase.solve(game, self.profile.ordering)) {
+                self.from_tablebase = true;
+                self.trace(TraceEvent::Tablebase { hp: solved.as_ref().map(|solution| solution.hp) });
+                self.solve = solved;
+                self.improved();
+                return;
+            }
+        }
+
+        if self.ranks_alternatives() {
+            // ranking lines needs the queue, whatever the strategy
+            if self.mode == SolveMode::Fast {
+                self.init_besthp(game);
+            }
+            self.alternatives = astar_k_best(game, self.besthp, self.objective, &self.profile, self.k_best, self.min_difference, &mut self.tracker, &mut self.stats);
+            self.solve = self.alternatives.first().cloned();
+            self.improved();
+            return;
+        }
+
+        if self.strategy == SearchStrategy::AStar {
+            // the fast cutoffs only bound the queue, whatever comes off it first is the best line
+            if self.mode == SolveMode::Fast {
+                self.init_besthp(game);
+            }
+            self.solve = astar_search(game, self.besthp, self.objective, &self.profile, &mut self.tracker, &mut self.stats);
+            self.improved();
+            return;
+        }
+
+        self.init_besthp(game);
+        self.search(game);
+
+        if self.mode == SolveMode::Exact && self.complete() {
+            // the fast line only seeds the bound, everything cheaper still gets searched
+            self.search_best = true;
+            self.besthp = 0;
+            self.stats.peak_table_size = self.stats.peak_table_size.max(self.checked_perms.len());
+            self.checked_perms.clear();
+            self.search(game);
+        }
+    }
+
+    fn search(&mut self, game: &D15Game) {
+        self.passes += 1;
+        self.trace(TraceEvent::Pass { pass: self.passes, besthp: self.besthp, search_best: self.search_best });
+        if self.threads > 1 {
+            self.search_parallel(game);
+            return;
+        }
+
+        for move_oper in self.profile.start_moves(game) {
+            let mut new_game = game.clone();
+            new_game.do_move(&move_oper);
+            let moves_done = vec![move_oper];
+            let cost = self.objective.move_cost(move_oper, game.hp - new_game.hp);
+
+            // counted apart so the trace can tell which start move the work went into
+            let mut total = std::mem::take(&mut self.stats);
+            self.solve(&new_game, &moves_done, cost);
+            self.stats.elapsed = self.tracker.elapsed();
+            self.stats.peak_table_size = self.checked_perms.len();
+            self.trace(TraceEvent::StartMove { start_move: move_oper, stats: &self.stats });
+            total.merge(&self.stats);
+            self.stats = total;
+        }
+    }
+
+    fn search_parallel(&mut self, game: &D15Game) {
+        let on_improvement = &mut self.on_improvement;
+        let trace = self.trace.as_ref();
+        let mut report = |solution: &Solution, nodes: u64| {
+            if let Some(trace) = trace {
+                trace.record(&TraceEvent::Improvement { moves: &solution.moves, hp: solution.hp, cost: solution.cost, nodes });
+            }
+            if let Some(on_improvement) = on_improvement.as_mut() {
+                on_improvement(&Improvement { solution, nodes });
+            }
+        };
+        let found = parallel_search(game, self.objective, self.profile, self.besthp, self.search_best, self.solve.clone(), self.threads, &mut self.tracker, &mut self.stats, trace, &mut report);
+
+        if found.is_some() {
+            self.solve = found;
+            if !self.search_best {
+                self.improved();
+            }
+        }
+    }
+
+    fn solve(&mut self, game: &D15Game, moves_done: &[Move], cost: u64) {
+            if !self.complete() {
+                return;
+            }
+            if game.hp <= self.besthp {
+                self.stats.besthp_prunes += 1;
+                return;
+            }
+            if game.is_dead() {
+                return;
+            }
+        if let Some(solution) = &self.solve {
+            if cost >= solution.cost {
+                return;
+            }
+        }
+
+        if game.check_win_2() {
+            self.solve = Some(Solution::new(self.start.as_ref().unwrap(), moves_done.to_vec(), cost));
+            self.improved();
+            if !self.search_best {
+                // above every hp a board can have, nothing gets searched any more
+                self.besthp = game.rules().start_hp_max + 1;
+            }
+            return;
+        }
+
+        let game_key = game.key();
+
+        match self.checked_perms.get(&game_key) {
+            Some(&seen_cost) if seen_cost <= cost => {
+                self.stats.transposition_hits += 1;
+                return;
+            },
+            _ => self.checked_perms.insert(game_key, cost),
+        };
+        if !self.tracker.expand() {
+            return;
+        }
+        self.stats.nodes += 1;
+        self.stats.max_depth = self.stats.max_depth.max(moves_done.len());
+
+        let moves = self.profile.moves(game);
+
+        for move_oper in &moves {
+            let mut new_game = game.clone();
+            let mut new_moves_done = moves_done.to_vec();
+            new_game.do_move(move_oper);
+            new_moves_done.push(*move_oper);
+            let new_cost = cost.saturating_add(self.objective.move_cost(*move_oper, game.hp - new_game.hp));
+            self.solve(&new_game, &new_moves_done, new_cost);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::astar::move_difference;
+
+    fn solved(solver: &mut Solver, magic_string: &str) -> (i16, u64) {
+        let solution = solver.solve_game(&magic_string.parse().unwrap()).unwrap();
+        (solution.hp, solution.cost)
+    }
+
+    #[test]
+    fn solves_boards_in_a_row_like_each_on_its_own() {
+        let mut solver = Solver::new(SolveMode::Exact, SearchStrategy::DepthFirst);
+        assert_eq!(solved(&mut solver, "60_4_3_5_3_5_3_3_3_5_4_A_B"), (48, 12));
+        assert_eq!(solved(&mut solver, "60_4_3_4_4_5_3_3_3_5_4_A_Y"), (44, 16));
+        assert!(solver.proven_optimal());
+    }
+
+    const BOARDS: [&str; 3] = ["60_4_3_5_3_5_3_3_3_5_4_A_B", "56_4_3_4_2_5_3_3_3_5_4_A_R", "60_4_3_3_3_5_3_3_3_5_4_A_Y"];
+
+    fn solver(mode: SolveMode, strategy: SearchStrategy, threads: usize) -> Solver {
+        Solver::builder().mode(mode).strategy(strategy).threads(threads).build()
+    }
+
+    #[test]
+    fn fast_finds_a_line_no_better_than_exact() {
+        for magic_string in BOARDS {
+            let mut exact = solver(SolveMode::Exact, SearchStrategy::DepthFirst, 1);
+            let best = solved(&mut exact, magic_string);
+            assert!(exact.proven_optimal());
+
+            let mut fast = solver(SolveMode::Fast, SearchStrategy::DepthFirst, 1);
+            let (hp, _) = solved(&mut fast, magic_string);
+            assert!(hp <= best.0 && hp > fast.profile.besthp_low, "{}", magic_string);
+            assert!(!fast.proven_optimal());
+        }
+    }
+
+    #[test]
+    fn astar_agrees_with_depth_first() {
+        for magic_string in BOARDS {
+            let best = solved(&mut solver(SolveMode::Exact, SearchStrategy::DepthFirst, 1), magic_string);
+            let mut astar = solver(SolveMode::Exact, SearchStrategy::AStar, 1);
+            assert_eq!(solved(&mut astar, magic_string), best, "{}", magic_string);
+            assert!(astar.proven_optimal());
+
+            // the cutoff only bounds the queue, the first line off it is the best one
+            assert_eq!(solved(&mut solver(SolveMode::Fast, SearchStrategy::AStar, 1), magic_string), best, "{}", magic_string);
+        }
+    }
+
+    #[test]
+    fn dead_boards_are_not_expanded() {
+        // every start move costs hp and kills, none of them gets expanded
+        for strategy in [SearchStrategy::DepthFirst, SearchStrategy::AStar] {
+            let mut solver = Solver::builder().mode(SolveMode::Exact).strategy(strategy).death_hp(59).build();
+            let result = solver.solve_game(&BOARDS[2].parse().unwrap());
+            assert!(matches!(result, Err(SolveError::Unsolvable { .. })), "{:?}", strategy);
+            if strategy == SearchStrategy::DepthFirst {
+                assert_eq!(solver.stats().nodes, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn nobody_wins_with_the_boss_on_the_edge() {
+        // the boss is on the left edge, the line puts a piece on the last tile of the row above instead
+        let magic_string = "63_0_6_5_1_3_4_4_1_1_7_A_Y";
+        let game: D15Game = magic_string.parse().unwrap();
+        assert!(game.is_boss_on_edge());
+        let line = crate::parse_moves("UP_DOG_DOG_CAT_DOG_LEFT_UP").unwrap();
+        let verification = crate::verify::verify(&game, &line);
+        assert!(verification.illegal.is_none() && !verification.won);
+        assert!(!verification.steps.last().unwrap().game().check_win());
+
+        for strategy in [SearchStrategy::DepthFirst, SearchStrategy::AStar] {
+            let mut solver = solver(SolveMode::Fast, strategy, 1);
+            assert!(matches!(solver.solve_game(&game), Err(SolveError::Unsolvable { .. })), "{:?}", strategy);
+        }
+    }
+
+    #[test]
+    fn alternatives_come_best_first_and_differ_enough() {
+        let magic_string = BOARDS[0];
+        let best = solved(&mut solver(SolveMode::Exact, SearchStrategy::DepthFirst, 1), magic_string);
+
+        let mut k_best = Solver::builder().mode(SolveMode::Exact).alternatives(4, 0).build();
+        assert_eq!(solved(&mut k_best, magic_string), best);
+        let alternatives = k_best.alternatives();
+        assert_eq!(alternatives.len(), 4);
+        assert!(alternatives.windows(2).all(|pair| pair[0].cost <= pair[1].cost));
+        assert!(alternatives.iter().all(|solution| solution.steps.last().unwrap().game().check_win_2()));
+
+        let mut diverse = Solver::builder().mode(SolveMode::Exact).alternatives(3, 3).build();
+        assert_eq!(solved(&mut diverse, magic_string), best);
+        let alternatives = diverse.alternatives();
+        assert_eq!(alternatives.len(), 3);
+        for (index, solution) in alternatives.iter().enumerate() {
+            for better in &alternatives[..index] {
+                assert!(move_difference(&better.moves, &solution.moves) >= 3);
+            }
+        }
+    }
+
+    #[test]
+    fn move_difference_counts_the_extra_moves() {
+        assert_eq!(move_difference(&[Move::UP, Move::DOG], &[Move::UP, Move::CAT]), 1);
+        assert_eq!(move_difference(&[Move::UP], &[Move::DOWN, Move::DOG, Move::CAT]), 3);
+        assert_eq!(move_difference(&[], &[]), 0);
+    }
+
+    #[test]
+    fn objectives_pick_their_own_best_line() {
+        let magic_string = "60_4_3_4_4_5_3_3_3_5_4_A_Y";
+        let mut max_hp = Solver::builder().mode(SolveMode::Exact).build();
+        let most_hp = max_hp.solve_game(&magic_string.parse().unwrap()).unwrap().clone();
+
+        for strategy in [SearchStrategy::DepthFirst, SearchStrategy::AStar] {
+            let mut fewest_moves = Solver::builder().mode(SolveMode::Exact).strategy(strategy).objective(SolveObjective::FewestMoves).build();
+            let shortest = fewest_moves.solve_game(&magic_string.parse().unwrap()).unwrap();
+            assert!(shortest.moves.len() <= most_hp.moves.len() && shortest.hp <= most_hp.hp, "{:?}", strategy);
+
+            // scaling every weight up must not change which line is cheapest
+            let mut huge = Solver::builder().mode(SolveMode::Exact).strategy(strategy).objective(SolveObjective::Weighted { hp: u32::MAX, moves: 0, risky: 0 }).build();
+            assert_eq!(huge.solve_game(&magic_string.parse().unwrap()).unwrap().hp, most_hp.hp, "{:?}", strategy);
+        }
+    }
+
+    #[test]
+    fn move_cost_weighs_hp_moves_and_risky_moves() {
+        let objective = SolveObjective::Weighted { hp: 3, moves: 5, risky: 7 };
+        assert_eq!(objective.move_cost(Move::UP, 2), 3 * 2 + 5);
+        assert_eq!(objective.move_cost(Move::SWITCH, 2), 3 * 2 + 5 + 7);
+        assert_eq!(objective.move_cost(Move::PASSTURN, 29), 3 * 29 + 5 + 7);
+        assert_eq!(SolveObjective::MaxHp.move_cost(Move::PASSTURN, 29), 29);
+        assert_eq!(SolveObjective::Weighted { hp: u32::MAX, moves: u32::MAX, risky: u32::MAX }.move_cost(Move::SWITCH, i16::MAX), u32::MAX as u64 * i16::MAX as u64 + 2 * u32::MAX as u64);
+    }
+
+    #[test]
+    fn one_move_more_outweighs_any_hp() {
+        for objective in [SolveObjective::FewestMoves, SolveObjective::FewestRiskyMoves] {
+            let move_oper = if objective == SolveObjective::FewestMoves { Move::UP } else { Move::SWITCH };
+            let longer = 3 * objective.move_cost(move_oper, 0);
+            // about from the highest hp there is down to the lowest death hp
+            let shorter = 2 * objective.move_cost(move_oper, i16::MAX);
+            assert!(shorter < longer, "{:?}", objective);
+        }
+    }
+
+    #[test]
+    fn stops_when_the_budget_runs_out() {
+        let game = "60_4_3_4_4_5_3_3_3_5_4_A_Y".parse().unwrap();
+        for strategy in [SearchStrategy::DepthFirst, SearchStrategy::AStar] {
+            let mut tight = Solver::builder().mode(SolveMode::Exact).strategy(strategy).budget(SolveBudget { max_nodes: Some(5), ..SolveBudget::default() }).build();
+            assert!(matches!(tight.solve_game(&game), Err(SolveError::OutOfBudget { reason: StopReason::NodeLimit, .. })), "{:?}", strategy);
+            assert!(!tight.complete());
+            assert!(!tight.proven_optimal());
+            assert!(tight.stats().nodes <= 6);
+
+            let mut roomy = Solver::builder().mode(SolveMode::Exact).strategy(strategy).budget(SolveBudget { max_nodes: Some(10_000_000), ..SolveBudget::default() }).build();
+            assert!(roomy.solve_game(&game).is_ok());
+            assert!(roomy.complete() && roomy.stop_reason().is_none(), "{:?}", strategy);
+        }
+
+        let cancelled = Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let mut solver = Solver::builder().mode(SolveMode::Exact).budget(SolveBudget { cancel: Some(cancelled), ..SolveBudget::default() }).build();
+        solver.do_solve(&game);
+        assert_eq!(solver.stop_reason(), Some(StopReason::Cancelled));
+    }
+
+    #[test]
+    fn reports_every_improvement_as_it_is_found() {
+        let game = "60_4_3_4_4_5_3_3_3_5_4_A_Y".parse().unwrap();
+        for threads in [1, 4] {
+            let improvements = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+            let mut solver = solver(SolveMode::Exact, SearchStrategy::DepthFirst, threads);
+            let seen = improvements.clone();
+            solver.set_on_improvement(move |improvement| seen.borrow_mut().push((improvement.solution.cost, improvement.nodes)));
+            let best = solver.solve_game(&game).unwrap().cost;
+
+            let improvements = improvements.borrow();
+            assert!(!improvements.is_empty());
+            assert!(improvements.windows(2).all(|pair| pair[1].0 < pair[0].0 && pair[1].1 >= pair[0].1), "{:?}", improvements);
+            assert_eq!(improvements.last().unwrap().0, best);
+        }
+    }
+
+    #[test]
+    fn threads_find_what_one_thread_finds() {
+        for magic_string in BOARDS {
+            let mut exact = solver(SolveMode::Exact, SearchStrategy::DepthFirst, 4);
+            assert_eq!(solved(&mut exact, magic_string), solved(&mut solver(SolveMode::Exact, SearchStrategy::DepthFirst, 1), magic_string), "{}", magic_string);
+            assert!(exact.proven_optimal());
+
+            // the earliest start move that wins is taken, however the threads are scheduled
+            let line = solver(SolveMode::Fast, SearchStrategy::DepthFirst, 1).solve_game(&magic_string.parse().unwrap()).unwrap().moves.clone();
+            let mut fast = solver(SolveMode::Fast, SearchStrategy::DepthFirst, 4);
+            assert_eq!(fast.solve_game(&magic_string.parse().unwrap()).unwrap().moves, line, "{}", magic_string);
+        }
+    }
+
+    #[test]
+    fn failed_solves_keep_their_stats() {
+        let mut solver = Solver::new(SolveMode::Fast, SearchStrategy::DepthFirst);
+        let stats = match solver.solve_game(&"50_0_0_4_4_5_3_3_3_5_4_A_Y".parse().unwrap()) {
+            Err(SolveError::Unsolvable { stats }) => stats,
+            other => panic!("{:?}", other.map(|solution| solution.hp)),
+        };
+        assert!(stats.nodes > 0 && stats.besthp_prunes > 0);
+        assert_eq!(&stats, solver.stats());
+    }
+}
@@ -0,0 +1,173 @@
+use std::fmt::{self, Write as _};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::budget::StopReason;
+use crate::game::Move;
+
+/// What a search did, see `Solver::stats`.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct SolveStats {
+    /// Boards expanded, what the node limit counts.
+    pub nodes: u64,
+    /// Boards skipped because the transposition table had them at no higher cost already.
+    pub transposition_hits: u64,
+    /// Boards dropped for being at or below `besthp`. Most of these on a position that couldn't be
+    /// solved means the fast cutoffs were too high for it.
+    pub besthp_prunes: u64,
+    /// Most moves deep any expanded board was.
+    pub max_depth: usize,
+    pub elapsed: Duration,
+    /// Most boards the transposition table held at once.
+    pub peak_table_size: usize,
+}
+
+impl SolveStats {
+    /// Adds up the counters of a search that ran alongside, e.g. another worker.
+    pub fn merge(&mut self, other: &SolveStats) {
+        self.nodes += other.nodes;
+        self.transposition_hits += other.transposition_hits;
+        self.besthp_prunes += other.besthp_prunes;
+        self.max_depth = self.max_depth.max(other.max_depth);
+        self.peak_table_size = self.peak_table_size.max(other.peak_table_size);
+    }
+
+    fn write_json_fields(&self, out: &mut String) {
+        let _ = write!(out, "\"nodes\":{},\"transposition_hits\":{},\"besthp_prunes\":{},\"max_depth\":{},\"elapsed_ms\":{},\"peak_table_size\":{}",
+            self.nodes, self.transposition_hits, self.besthp_prunes, self.max_depth, self.elapsed.as_millis(), self.peak_table_size);
+    }
+}
+
+impl fmt::Display for SolveStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "nodes: {}, transposition hits: {}, besthp prunes: {}, max depth: {}, elapsed: {} ms, peak table size: {}",
+            self.nodes, self.transposition_hits, self.besthp_prunes, self.max_depth, self.elapsed.as_millis(), self.peak_table_size)
+    }
+}
+
+/// One line of a `SearchTrace`.
+pub enum TraceEvent<'a> {
+    /// A solve starts on a board with `hp`.
+    Solve { hp: i16, mode: &'a str, strategy: &'a str, threads: usize },
+    /// The tablebase answered, nothing gets searched.
+    Tablebase { hp: Option<i16> },
+    /// A search over the whole tree starts, lines at or below `besthp` get cut off.
+    Pass { pass: u32, besthp: i16, search_best: bool },
+    /// The depth-first search is done with everything after one start move.
+    StartMove { start_move: Move, stats: &'a SolveStats },
+    /// A line better than the ones before.
+    Improvement { moves: &'a [Move], hp: i16, cost: u64, nodes: u64 },
+    /// The solve is over, `hp` of the line it ended with.
+    Done { hp: Option<i16>, stopped: Option<StopReason>, stats: &'a SolveStats },
+}
+
+impl TraceEvent<'_> {
+    fn to_json(&self) -> String {
+        let mut out = String::new();
+        let _ = match self {
+            TraceEvent::Solve { hp, mode, strategy, threads } =>
+                write!(out, "{{\"event\":\"solve\",\"hp\":{},\"mode\":\"{}\",\"strategy\":\"{}\",\"threads\":{}}}", hp, mode, strategy, threads),
+            TraceEvent::Tablebase { hp } =>
+                write!(out, "{{\"event\":\"tablebase\",\"hp\":{}}}", json_option(hp)),
+            TraceEvent::Pass { pass, besthp, search_best } =>
+                write!(out, "{{\"event\":\"pass\",\"pass\":{},\"besthp\":{},\"search_best\":{}}}", pass, besthp, search_best),
+            TraceEvent::StartMove { start_move, stats } => {
+                let _ = write!(out, "{{\"event\":\"start_move\",\"move\":\"{:?}\",", start_move);
+                stats.write_json_fields(&mut out);
+                write!(out, "}}")
+            },
+            TraceEvent::Improvement { moves, hp, cost, nodes } => {
+                let moves: Vec<String> = moves.iter().map(|move_oper| format!("\"{:?}\"", move_oper)).collect();
+                write!(out, "{{\"event\":\"improvement\",\"moves\":[{}],\"hp\":{},\"cost\":{},\"nodes\":{}}}", moves.join(","), hp, cost, nodes)
+            },
+            TraceEvent::Done { hp, stopped, stats } => {
+                let stopped = stopped.map(|reason| format!("\"{:?}\"", reason));
+                let _ = write!(out, "{{\"event\":\"done\",\"hp\":{},\"stopped\":{},", json_option(hp), json_option(&stopped));
+                stats.write_json_fields(&mut out);
+                write!(out, "}}")
+            },
+        };
+        out
+    }
+}
+
+fn json_option<T: fmt::Display>(value: &Option<T>) -> String {
+    value.as_ref().map_or_else(|| "null".to_string(), |value| value.to_string())
+}
+
+/// Writes what a search does as JSON lines, one `TraceEvent` each, e.g. to find out why a position
+/// couldn't be solved. Clones write to the same writer, so workers on other threads can trace too.
+#[derive(Clone)]
+pub struct SearchTrace {
+    out: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl SearchTrace {
+    pub fn new<W: Write + Send + 'static>(out: W) -> SearchTrace {
+        SearchTrace { out: Arc::new(Mutex::new(Box::new(out))) }
+    }
+
+    /// Write errors are dropped, a trace that can't be written must not fail the solve.
+    pub fn record(&self, event: &TraceEvent) {
+        let mut out = self.out.lock().unwrap();
+        let _ = writeln!(out, "{}", event.to_json());
+        let _ = out.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Collects what a `SearchTrace` writes.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn stats(nodes: u64, max_depth: usize) -> SolveStats {
+        SolveStats { nodes, transposition_hits: 2, besthp_prunes: 3, max_depth, elapsed: Duration::from_millis(7), peak_table_size: nodes as usize }
+    }
+
+    #[test]
+    fn merge_adds_counts_and_keeps_the_peaks() {
+        let mut total = stats(10, 4);
+        total.merge(&stats(5, 9));
+        assert_eq!((total.nodes, total.transposition_hits, total.besthp_prunes, total.max_depth, total.peak_table_size), (15, 4, 6, 9, 10));
+        assert_eq!(total.elapsed, Duration::from_millis(7), "time is taken by whoever runs the workers");
+    }
+
+    #[test]
+    fn writes_one_json_line_per_event() {
+        let captured = Captured::default();
+        let trace = SearchTrace::new(captured.clone());
+        trace.clone().record(&TraceEvent::Pass { pass: 1, besthp: 40, search_best: false });
+        trace.record(&TraceEvent::Improvement { moves: &[Move::UP, Move::DOG], hp: 44, cost: 16, nodes: 12 });
+        trace.record(&TraceEvent::Done { hp: None, stopped: Some(StopReason::NodeLimit), stats: &stats(10, 4) });
+
+        let written = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(written.lines().collect::<Vec<_>>(), [
+            r#"{"event":"pass","pass":1,"besthp":40,"search_best":false}"#,
+            r#"{"event":"improvement","moves":["UP","DOG"],"hp":44,"cost":16,"nodes":12}"#,
+            r#"{"event":"done","hp":null,"stopped":"NodeLimit","nodes":10,"transposition_hits":2,"besthp_prunes":3,"max_depth":4,"elapsed_ms":7,"peak_table_size":10}"#,
+        ]);
+        for line in written.lines() {
+            assert!(serde_json::from_str::<serde_json::Value>(line).is_ok(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn shows_every_count() {
+        assert_eq!(stats(10, 4).to_string(), "nodes: 10, transposition hits: 2, besthp prunes: 3, max depth: 4, elapsed: 7 ms, peak table size: 10");
+    }
+}
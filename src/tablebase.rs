@@ -0,0 +1,511 @@
+//! Precomputed best lines, see `Tablebase`.
+//!
+//! File format, all numbers little-endian:
+//!
+//! | offset | size       | field                                                            |
+//! |--------|------------|------------------------------------------------------------------|
+//! | 0      | 8          | magic `ED15TB` and two zero bytes                                |
+//! | 8      | 2          | format version, `FORMAT_VERSION`                                 |
+//! | 10     | 2          | `game::RULES_VERSION` it was built for                           |
+//! | 12     | 16         | `Rules` it was built for, 8 × i16, see `Rules::constants`        |
+//! | 28     | 2          | `min_hp`, i16                                                    |
+//! | 30     | 2          | death hp of the boards, i16                                      |
+//! | 32     | 8          | number of records, u64                                           |
+//! | 40     | 8          | checksum of the header and bucket checksums, see `checksum`      |
+//! | 48     | 257 × 4    | index: first record of each of the 256 buckets, then the count   |
+//! | 1076   | 256 × 8    | checksum of each bucket's records                                |
+//! | 3124   | count × 12 | records sorted by state key: key u64, best hp i16, distance u16  |
+//!
+//! A record's bucket is the top 8 of the 49 bits of its `StateKey`, so a lookup only has to binary
+//! search the records of one bucket. Loading only checks everything up to the records, each bucket
+//! is checked the first time a lookup reads it, so a mapped file is only read as far as lookups
+//! go. A bucket that doesn't match its checksum answers nothing. Only boards reachable from the starts it was generated from
+//! are in a file, see `Tablebase`. Files for another `RULES_VERSION` or other format versions are
+//! refused, and a tablebase never answers for boards with other `Rules` or another death hp than it
+//! was built for, or other pets than `D15_PETS`.
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use hash_hasher::HashBuildHasher;
+
+use crate::error::TablebaseError;
+use crate::game::{D15Game, Move, StateKey, DEATH_HP, RULES_VERSION};
+use crate::pets::D15_PETS;
+use crate::profiles::StrategyProfile;
+use crate::rules::{Rules, DEFAULT_RULES};
+use crate::solver::{MoveOrdering, Solution};
+
+const MAGIC: &[u8; 8] = b"ED15TB\0\0";
+pub const FORMAT_VERSION: u16 = 1;
+
+const RULES_OFFSET: usize = 12;
+const RULE_CONSTANT_COUNT: usize = 8;
+const MIN_HP_OFFSET: usize = 28;
+const DEATH_HP_OFFSET: usize = 30;
+const COUNT_OFFSET: usize = 32;
+const CHECKSUM_OFFSET: usize = 40;
+const INDEX_OFFSET: usize = 48;
+const BUCKETS: usize = 256;
+const BUCKET_SUMS_OFFSET: usize = INDEX_OFFSET + (BUCKETS + 1) * 4;
+const RECORDS_OFFSET: usize = BUCKET_SUMS_OFFSET + BUCKETS * 8;
+/// Bits of the packed `StateKey` below the bucket.
+const BUCKET_SHIFT: u32 = 41;
+const RECORD_SIZE: usize = 12;
+
+/// `Entry::hp` of a board that can't be won above the tablebase's `min_hp`. Below any hp a win
+/// can end with, whatever the death hp.
+const NO_WIN: i16 = i16::MIN;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Entry {
+    /// Most hp any line from the board wins with, `NO_WIN` if none stays above `min_hp`.
+    pub hp: i16,
+    /// Moves the shortest of those lines takes.
+    pub distance: u16,
+}
+
+/// FNV-1a over 8-byte little-endian words, the last one of each part padded with zeroes.
+fn checksum(parts: &[&[u8]]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for part in parts {
+        for word in part.chunks(8) {
+            let mut padded = [0; 8];
+            padded[..word.len()].copy_from_slice(word);
+            hash = (hash ^ u64::from_le_bytes(padded)).wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+fn record_offset(index: usize) -> usize {
+    RECORDS_OFFSET + index * RECORD_SIZE
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+enum Storage {
+    Bytes(Vec<u8>),
+    #[cfg(not(target_arch = "wasm32"))]
+    Mapped(memmap2::Mmap),
+}
+
+impl Storage {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Storage::Bytes(bytes) => bytes,
+            #[cfg(not(target_arch = "wasm32"))]
+            Storage::Mapped(mmap) => mmap,
+        }
+    }
+}
+
+/// Best line for every board reachable from a set of starts, the way `SolveMode::Exact` with
+/// `SolveObjective::MaxHp` would find it if it tried every legal move. It knows the game, not the
+/// search's `StrategyProfile`s, so it also has the lines with a PASSTURN the search wouldn't try.
+///
+/// All of the 64 tiles for each piece, both player states, the colors and the hp range together are
+/// far too many boards, but the boss never moves and only a small part of the rest can be reached
+/// from any one start. So `generate` walks forward from its starts to collect that part, then fills
+/// in the values backwards from the won boards. Every move costs hp, so doing that in order of
+/// rising hp only ever looks at boards that are done already.
+///
+/// Even that part runs into the hundreds of millions of boards from a start at 50 hp, so lines are
+/// cut off at `min_hp` like the search does at `besthp`. A win above it is still the best there is,
+/// any line that goes below ends with less hp. Only `NO_WIN` means less: no win above `min_hp`.
+///
+/// So a tablebase only knows the boards its starts lead to, not the whole state space: a board no
+/// start reaches, e.g. one with the pets elsewhere or more hp than any start, isn't in it and
+/// `Solver` searches it as if there were no tablebase. Generating from more starts covers more.
+pub struct Tablebase {
+    storage: Storage,
+    rules: Rules,
+    min_hp: i16,
+    death_hp: i16,
+    count: usize,
+    /// `UNCHECKED`, `INTACT` or `DAMAGED` for each bucket.
+    buckets: Vec<AtomicU8>,
+}
+
+const UNCHECKED: u8 = 0;
+const INTACT: u8 = 1;
+const DAMAGED: u8 = 2;
+
+/// Boards `game` can move to that stay above `min_hp`, in `ordering`. All `legal_moves`, PASSTURN
+/// at any hp too.
+fn successors(game: &D15Game, min_hp: i16, ordering: MoveOrdering) -> impl Iterator<Item = (Move, D15Game)> + '_ {
+    let every_move = StrategyProfile { ordering, passturn_window_end: i16::MAX, passturn_window_start_low: i16::MIN, ..StrategyProfile::DEFAULT };
+    every_move.moves(game).into_iter().filter_map(move |move_oper| {
+        let mut new_game = game.clone();
+        new_game.do_move(&move_oper);
+        if new_game.hp > min_hp { Some((move_oper, new_game)) } else { None }
+    })
+}
+
+/// Entry of a board from the entries of the boards it can move to.
+fn best_next(next: impl Iterator<Item = Entry>) -> Entry {
+    next.filter(|entry| entry.hp != NO_WIN)
+        .max_by_key(|entry| (entry.hp, Reverse(entry.distance)))
+        .map_or(Entry { hp: NO_WIN, distance: 0 }, |best| Entry { hp: best.hp, distance: best.distance + 1 })
+}
+
+impl Tablebase {
+    /// `min_hp` below the starts' death hp counts as that, a line has to stay alive to win. The
+    /// starts all need the same rules and death hp, the tablebase is only good for those, and the
+    /// D15 pets.
+    pub fn generate(starts: &[D15Game], min_hp: i16) -> Tablebase {
+        assert!(starts.iter().all(|game| *game.pets() == D15_PETS), "tablebases only know the D15 pets");
+        let rules = starts.first().map_or(&DEFAULT_RULES, D15Game::rules);
+        assert!(starts.iter().all(|game| game.rules() == rules), "tablebase starts with different rules");
+        let death_hp = starts.first().map_or(DEATH_HP, D15Game::death_hp);
+        assert!(starts.iter().all(|game| game.death_hp() == death_hp), "tablebase starts with different death hp");
+        let min_hp = min_hp.max(death_hp);
+        let mut reachable: HashSet<StateKey, HashBuildHasher> = HashSet::with_hasher(HashBuildHasher::default());
+        // the search always makes a move, so a start that is won already still needs the boards after it
+        let mut pending: Vec<D15Game> = starts.iter().filter(|game| game.hp > min_hp).flat_map(|game| {
+            if game.check_win_2() { successors(game, min_hp, MoveOrdering::Legal).map(|(_, new_game)| new_game).collect() } else { vec![game.clone()] }
+        }).collect();
+        while let Some(game) = pending.pop() {
+            if !reachable.insert(game.key()) || game.check_win_2() {
+                continue;
+            }
+            pending.extend(successors(&game, min_hp, MoveOrdering::Legal).map(|(_, new_game)| new_game).filter(|new_game| !reachable.contains(&new_game.key())));
+        }
+
+        let mut boards: Vec<D15Game> = reachable.into_iter().map(|key| D15Game::from_key(key, rules).with_death_hp(death_hp)).collect();
+        boards.sort_by_key(|game| game.hp);
+
+        let mut entries: HashMap<StateKey, Entry, HashBuildHasher> = HashMap::with_capacity_and_hasher(boards.len(), HashBuildHasher::default());
+        for game in boards {
+            let entry = if game.check_win_2() {
+                Entry { hp: game.hp, distance: 0 }
+            } else {
+                best_next(successors(&game, min_hp, MoveOrdering::Legal).map(|(_, new_game)| entries[&new_game.key()]))
+            };
+            entries.insert(game.key(), entry);
+        }
+
+        let mut records: Vec<(u64, Entry)> = entries.into_iter().map(|(key, entry)| (key.packed(), entry)).collect();
+        records.sort_unstable_by_key(|&(key, _)| key);
+        Tablebase::from_records(rules, min_hp, death_hp, &records)
+    }
+
+    fn from_records(rules: &Rules, min_hp: i16, death_hp: i16, records: &[(u64, Entry)]) -> Tablebase {
+        let mut bytes = Vec::with_capacity(RECORDS_OFFSET + records.len() * RECORD_SIZE);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&RULES_VERSION.to_le_bytes());
+        for constant in rules.constants().iter() {
+            bytes.extend_from_slice(&constant.to_le_bytes());
+        }
+        bytes.extend_from_slice(&min_hp.to_le_bytes());
+        bytes.extend_from_slice(&death_hp.to_le_bytes());
+        bytes.extend_from_slice(&(records.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&[0; 8]);
+
+        let mut index = Vec::with_capacity(BUCKETS + 1);
+        let mut record = 0;
+        for bucket in 0..=BUCKETS as u64 {
+            while record < records.len() && records[record].0 >> BUCKET_SHIFT < bucket {
+                record += 1;
+            }
+            index.push(record);
+            bytes.extend_from_slice(&(record as u32).to_le_bytes());
+        }
+        bytes.resize(RECORDS_OFFSET, 0);
+        for (key, entry) in records {
+            bytes.extend_from_slice(&key.to_le_bytes());
+            bytes.extend_from_slice(&entry.hp.to_le_bytes());
+            bytes.extend_from_slice(&entry.distance.to_le_bytes());
+        }
+
+        for bucket in 0..BUCKETS {
+            let sum = checksum(&[&bytes[record_offset(index[bucket])..record_offset(index[bucket + 1])]]);
+            bytes[BUCKET_SUMS_OFFSET + bucket * 8..BUCKET_SUMS_OFFSET + (bucket + 1) * 8].copy_from_slice(&sum.to_le_bytes());
+        }
+        let sum = checksum(&[&bytes[..CHECKSUM_OFFSET], &bytes[INDEX_OFFSET..RECORDS_OFFSET]]);
+        bytes[CHECKSUM_OFFSET..INDEX_OFFSET].copy_from_slice(&sum.to_le_bytes());
+        // written just now, nothing to check
+        let buckets = (0..BUCKETS).map(|_| AtomicU8::new(INTACT)).collect();
+        Tablebase { storage: Storage::Bytes(bytes), rules: rules.clone(), min_hp, death_hp, count: records.len(), buckets }
+    }
+
+    /// Checks the header, index and bucket checksums, the way every tablebase gets loaded. The
+    /// records are left for `bucket_intact`.
+    fn from_storage(storage: Storage) -> Result<Tablebase, TablebaseError> {
+        let bytes = storage.bytes();
+        if bytes.len() >= MAGIC.len() && &bytes[..MAGIC.len()] != MAGIC {
+            return Err(TablebaseError::BadMagic);
+        }
+        if bytes.len() < RECORDS_OFFSET {
+            return Err(TablebaseError::Truncated);
+        }
+        let version = read_u16(bytes, 8);
+        if version != FORMAT_VERSION {
+            return Err(TablebaseError::UnsupportedVersion { found: version });
+        }
+        if read_u16(bytes, 10) != RULES_VERSION {
+            return Err(TablebaseError::RulesMismatch);
+        }
+        let count: usize = read_u64(bytes, COUNT_OFFSET).try_into().map_err(|_| TablebaseError::Corrupt)?;
+        let size = count.checked_mul(RECORD_SIZE).and_then(|records| records.checked_add(RECORDS_OFFSET)).ok_or(TablebaseError::Corrupt)?;
+        if bytes.len() != size {
+            return Err(TablebaseError::Truncated);
+        }
+        if checksum(&[&bytes[..CHECKSUM_OFFSET], &bytes[INDEX_OFFSET..RECORDS_OFFSET]]) != read_u64(bytes, CHECKSUM_OFFSET) {
+            return Err(TablebaseError::ChecksumMismatch);
+        }
+        // lookups trust the index, it has to stay within the records
+        let index: Vec<usize> = (0..=BUCKETS).map(|bucket| read_u32(bytes, INDEX_OFFSET + bucket * 4) as usize).collect();
+        if index.windows(2).any(|pair| pair[0] > pair[1]) || index[BUCKETS] != count {
+            return Err(TablebaseError::Corrupt);
+        }
+
+        let mut constants = [0; RULE_CONSTANT_COUNT];
+        for (index, constant) in constants.iter_mut().enumerate() {
+            *constant = read_u16(bytes, RULES_OFFSET + index * 2) as i16;
+        }
+        let rules = Rules::from_constants(constants);
+        let min_hp = read_u16(bytes, MIN_HP_OFFSET) as i16;
+        let death_hp = read_u16(bytes, DEATH_HP_OFFSET) as i16;
+        let buckets = (0..BUCKETS).map(|_| AtomicU8::new(UNCHECKED)).collect();
+        Ok(Tablebase { storage, rules, min_hp, death_hp, count, buckets })
+    }
+
+    /// A tablebase file read into memory, e.g. fetched into an `ArrayBuffer` in the browser.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Tablebase, TablebaseError> {
+        Tablebase::from_storage(Storage::Bytes(bytes))
+    }
+
+    /// Maps a tablebase file into memory instead of reading it, records are only paged in once a
+    /// lookup touches their bucket.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open(path: &std::path::Path) -> Result<Tablebase, TablebaseError> {
+        let file = std::fs::File::open(path)?;
+        // Safety: the map is only read. Changing the file underneath it is on whoever does that,
+        // like for any mapped file, and the checksums catch changes made before a bucket is read.
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+        Tablebase::from_storage(Storage::Mapped(mmap))
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(self.storage.bytes())
+    }
+
+    /// The rules it was built for. Files don't keep the start hp range, a loaded one has the
+    /// default.
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    pub fn min_hp(&self) -> i16 {
+        self.min_hp
+    }
+
+    /// Death hp of the boards it has answers for, see `D15Game::with_death_hp`.
+    pub fn death_hp(&self) -> i16 {
+        self.death_hp
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Records of `bucket`, as index range.
+    fn bucket_records(&self, bucket: usize) -> (usize, usize) {
+        let bytes = self.storage.bytes();
+        (read_u32(bytes, INDEX_OFFSET + bucket * 4) as usize, read_u32(bytes, INDEX_OFFSET + (bucket + 1) * 4) as usize)
+    }
+
+    /// Whether the records of `bucket` match their checksum, worked out on the first call.
+    fn bucket_intact(&self, bucket: usize) -> bool {
+        let state = self.buckets[bucket].load(Ordering::Relaxed);
+        if state != UNCHECKED {
+            return state == INTACT;
+        }
+        let bytes = self.storage.bytes();
+        let (first, end) = self.bucket_records(bucket);
+        let intact = checksum(&[&bytes[record_offset(first)..record_offset(end)]]) == read_u64(bytes, BUCKET_SUMS_OFFSET + bucket * 8);
+        // threads checking the same bucket at once come to the same answer
+        self.buckets[bucket].store(if intact { INTACT } else { DAMAGED }, Ordering::Relaxed);
+        intact
+    }
+
+    /// `None` if the board wasn't reachable from any start the tablebase was generated for, is
+    /// played by other rules, pets or death hp, or its bucket of the file is damaged.
+    pub(crate) fn get(&self, game: &D15Game) -> Option<Entry> {
+        if game.rules().constants() != self.rules.constants() || *game.pets() != D15_PETS || game.death_hp() != self.death_hp {
+            return None;
+        }
+        let bytes = self.storage.bytes();
+        let key = game.key().packed();
+        let bucket = (key >> BUCKET_SHIFT) as usize;
+        if !self.bucket_intact(bucket) {
+            return None;
+        }
+
+        let (mut low, mut high) = self.bucket_records(bucket);
+        while low < high {
+            let middle = (low + high) / 2;
+            let offset = record_offset(middle);
+            let record_key = read_u64(bytes, offset);
+            if record_key < key {
+                low = middle + 1;
+            } else if record_key > key {
+                high = middle;
+            } else {
+                return Some(Entry { hp: read_u16(bytes, offset + 8) as i16, distance: read_u16(bytes, offset + 10) });
+            }
+        }
+        None
+    }
+
+    /// Follows the tablebase from `game` to the end of its best line, the first one in `ordering`
+    /// among equally good ones. `None` if the tablebase can't tell: the board isn't in it, there's
+    /// no win above a `min_hp` above the death hp, or the line breaks off, which only a tablebase
+    /// that doesn't fit the boards can do. `Some(None)` if the board can't be won at all.
+    pub fn solve(&self, game: &D15Game, ordering: MoveOrdering) -> Option<Option<Solution>> {
+        let entry = if game.check_win_2() {
+            // like the search, don't stop before the first move
+            let next: Option<Vec<Entry>> = successors(game, self.min_hp, ordering).map(|(_, new_game)| self.get(&new_game)).collect();
+            best_next(next?.into_iter())
+        } else {
+            self.get(game)?
+        };
+        if entry.hp == NO_WIN {
+            // nothing was cut off above the death hp, so there is no win at all
+            return if self.min_hp <= self.death_hp { Some(None) } else { None };
+        }
+
+        let mut moves = Vec::with_capacity(entry.distance as usize);
+        let mut current = game.clone();
+        for distance in (0..entry.distance).rev() {
+            let (move_oper, next) = successors(&current, self.min_hp, ordering)
+                .find(|(_, new_game)| self.get(new_game) == Some(Entry { hp: entry.hp, distance }))?;
+            moves.push(move_oper);
+            current = next;
+        }
+        Some(Some(Solution::new(game, moves, (game.hp - entry.hp) as u64)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: &str = "60_4_3_5_3_5_3_3_3_5_4_A_B";
+
+    fn start() -> D15Game {
+        START.parse().unwrap()
+    }
+
+    fn generated() -> Tablebase {
+        Tablebase::generate(&[start()], 44)
+    }
+
+    #[test]
+    fn solves_like_an_exact_search() {
+        let tablebase = generated();
+        let solution = tablebase.solve(&start(), MoveOrdering::Legal).unwrap().unwrap();
+        assert_eq!((solution.hp, solution.moves.len()), (48, 6));
+        assert!(solution.steps.last().unwrap().game().check_win_2());
+    }
+
+    #[test]
+    fn only_answers_for_its_own_boards() {
+        let tablebase = generated();
+        assert!(tablebase.get(&start()).is_some());
+        assert!(tablebase.get(&start().with_death_hp(46)).is_none());
+        assert!(tablebase.solve(&start().with_death_hp(46), MoveOrdering::Legal).is_none());
+        let other_rules = Rules { sword_damage: 5, ..Rules::DEFAULT }.leak();
+        assert!(tablebase.get(&start().with_rules(other_rules)).is_none());
+        assert!(tablebase.get(&"60_4_3_2_3_5_3_3_3_5_4_A_B".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    fn no_win_only_when_every_hp_above_death_is_covered() {
+        let mut game = start().with_death_hp(-6);
+        game.hp = 1;
+        // lines down to -5 hp are still alive, a table that stops at 0 can't rule them out
+        assert!(Tablebase::generate(std::slice::from_ref(&game), 0).solve(&game, MoveOrdering::Legal).is_none());
+        assert!(matches!(Tablebase::generate(std::slice::from_ref(&game), -6).solve(&game, MoveOrdering::Legal), Some(None)));
+    }
+
+    fn bytes(tablebase: &Tablebase) -> Vec<u8> {
+        let mut bytes = vec![];
+        tablebase.write_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn loads_what_it_wrote() {
+        let tablebase = generated();
+        let loaded = Tablebase::from_bytes(bytes(&tablebase)).unwrap();
+        assert_eq!(loaded.len(), tablebase.len());
+        assert_eq!((loaded.min_hp(), loaded.death_hp()), (44, DEATH_HP));
+        assert!(loaded.rules() == tablebase.rules());
+        assert!(loaded.get(&start()) == tablebase.get(&start()));
+        assert_eq!(bytes(&loaded), bytes(&tablebase));
+    }
+
+    #[test]
+    fn damaged_records_only_lose_their_bucket() {
+        let tablebase = generated();
+        let good = bytes(&tablebase);
+        let bucket = (start().key().packed() >> BUCKET_SHIFT) as usize;
+        let (first, _) = tablebase.bucket_records(bucket);
+        let mut flipped = good.clone();
+        flipped[record_offset(first) + 8] ^= 1;
+
+        let loaded = Tablebase::from_bytes(flipped).unwrap();
+        assert!(loaded.get(&start()).is_none());
+        // the color is in the bucket bits and changes with every move
+        let (_, next) = successors(&start(), tablebase.min_hp(), MoveOrdering::Legal).find(|(_, next)| tablebase.get(next).is_some()).unwrap();
+        assert_ne!(next.key().packed() >> BUCKET_SHIFT, bucket as u64);
+        assert!(loaded.get(&next) == tablebase.get(&next));
+    }
+
+    #[test]
+    fn refuses_damaged_files() {
+        let good = bytes(&generated());
+
+        let mut flipped = good.clone();
+        flipped[MIN_HP_OFFSET] ^= 1;
+        assert!(matches!(Tablebase::from_bytes(flipped), Err(TablebaseError::ChecksumMismatch)));
+        let mut flipped = good.clone();
+        flipped[BUCKET_SUMS_OFFSET] ^= 1;
+        assert!(matches!(Tablebase::from_bytes(flipped), Err(TablebaseError::ChecksumMismatch)));
+
+        assert!(matches!(Tablebase::from_bytes(good[..good.len() - 1].to_vec()), Err(TablebaseError::Truncated)));
+        assert!(matches!(Tablebase::from_bytes(good[..RECORDS_OFFSET - 1].to_vec()), Err(TablebaseError::Truncated)));
+
+        let mut not_a_tablebase = good.clone();
+        not_a_tablebase[0] = b'X';
+        assert!(matches!(Tablebase::from_bytes(not_a_tablebase), Err(TablebaseError::BadMagic)));
+
+        let mut newer = good.clone();
+        newer[8..10].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(Tablebase::from_bytes(newer), Err(TablebaseError::UnsupportedVersion { found }) if found == FORMAT_VERSION + 1));
+
+        let mut overflowing = good;
+        overflowing[COUNT_OFFSET..COUNT_OFFSET + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(Tablebase::from_bytes(overflowing), Err(TablebaseError::Corrupt)));
+    }
+}
@@ -0,0 +1,103 @@
+use std::fmt;
+
+use crate::error::IllegalMove;
+use crate::game::{D15Game, Move};
+use crate::solver::Step;
+
+/// What playing a line on a board came to, see `verify`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Verification {
+    /// The board after each move that got played.
+    pub steps: Vec<Step>,
+    /// The first move the board didn't offer. Nothing from there on is played.
+    pub illegal: Option<IllegalStep>,
+    /// Hp after the last move played.
+    pub hp: i16,
+    /// Whether the board after the last move played surrounds the boss.
+    pub won: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IllegalStep {
+    /// Counted from 0.
+    pub index: usize,
+    pub move_oper: Move,
+    pub reason: IllegalMove,
+}
+
+impl Verification {
+    /// The whole line got played and wins.
+    pub fn valid(&self) -> bool {
+        self.illegal.is_none() && self.won
+    }
+}
+
+impl fmt::Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.illegal {
+            Some(IllegalStep { index, move_oper, reason }) => write!(f, "move {} ({:?}) is not possible there, {} - {} hp left before it", index + 1, move_oper, reason, self.hp),
+            None if self.won => write!(f, "wins with {} hp left", self.hp),
+            None => write!(f, "does not win, {} hp left after the last move", self.hp),
+        }
+    }
+}
+
+/// Plays `moves` from `game` the way the solver would, each one has to be among
+/// `D15Game::legal_moves` of the board before it. For checking a line found for another board, or
+/// by hand.
+pub fn verify(game: &D15Game, moves: &[Move]) -> Verification {
+    let mut current = game.clone();
+    let mut steps = Vec::with_capacity(moves.len());
+    let mut illegal = None;
+    for (index, &move_oper) in moves.iter().enumerate() {
+        if let Err(reason) = current.try_do_move(move_oper) {
+            illegal = Some(IllegalStep { index, move_oper, reason });
+            break;
+        }
+        steps.push(Step::after(move_oper, &current));
+    }
+    Verification { steps, illegal, hp: current.hp, won: current.check_win_2() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Entity;
+    use crate::parse_moves;
+
+    fn verified(magic_string: &str, moves: &str) -> Verification {
+        verify(&magic_string.parse().unwrap(), &parse_moves(moves).unwrap())
+    }
+
+    #[test]
+    fn accepts_a_winning_line() {
+        let verification = verified("60_4_3_4_4_5_3_3_3_5_4_A_Y", "DRAGON_DOWN_DRAGON_CAT_DOWN_DRAGON_CAT_DOG_");
+        assert!(verification.valid());
+        assert_eq!(verification.hp, 44);
+        assert_eq!(verification.steps.len(), 8);
+        assert_eq!(verification.to_string(), "wins with 44 hp left");
+    }
+
+    #[test]
+    fn stops_at_the_first_illegal_move() {
+        let verification = verified("60_4_3_4_4_5_3_3_3_5_4_A_Y", "UP_DOWN_DOWN_LEFT_");
+        assert_eq!(verification.illegal, Some(IllegalStep { index: 2, move_oper: Move::DOWN, reason: IllegalMove::BlockedBy(Entity::BOSS) }));
+        assert_eq!(verification.steps.len(), 2);
+        assert!(!verification.valid());
+    }
+
+    #[test]
+    fn tells_a_line_that_does_not_win() {
+        let verification = verified("60_4_3_4_4_5_3_3_3_5_4_A_Y", "UP_");
+        assert_eq!(verification.illegal, None);
+        assert!(!verification.won);
+        assert_eq!(verification.to_string(), "does not win, 58 hp left after the last move");
+    }
+
+    #[test]
+    fn accepts_passturn_outside_of_the_search_window() {
+        let verification = verified("150_4_3_4_4_5_3_3_3_5_4_S_Y", "PASSTURN_");
+        assert_eq!(verification.illegal, None);
+        assert_eq!(verification.hp, 121);
+    }
+}